@@ -0,0 +1,61 @@
+//! Benchmarks of the throughput of `TwitterStream`.
+//!
+//! Run with `cargo bench -p twitter-stream-bench` and
+//! `cargo bench -p twitter-stream-bench --features simd` to compare the portable and
+//! the accelerated validation of the lines. The `blank` case consists only of keep-alive signals
+//! of the same total length as the `ascii` case.
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use futures::executor::block_on;
+use futures::TryStreamExt;
+
+/// Builds a response body with `n` messages, interleaved with keep-alive signals.
+fn body(n: usize, message: &str) -> Vec<u8> {
+    let mut body = Vec::new();
+    for i in 0..n {
+        body.extend_from_slice(message.as_bytes());
+        body.extend_from_slice(b"\r\n");
+        if i % 16 == 0 {
+            body.extend_from_slice(b"\r\n");
+        }
+    }
+    body
+}
+
+fn stream(c: &mut Criterion) {
+    let ascii = format!(
+        r#"{{"created_at":"Thu Apr 06 15:24:15 +0000 2017","id_str":"850006245121695744","text":"{}","user":{{"id":6253282}}}}"#,
+        "Just another Tweet with some text in it. ".repeat(6)
+    );
+    let non_ascii = ascii.replace("some text", "テキストと絵文字 😀");
+    let blank = " ".repeat(ascii.len());
+
+    let mut group = c.benchmark_group("stream");
+    for (name, message) in &[("ascii", ascii), ("non_ascii", non_ascii), ("blank", blank)] {
+        let body = body(1000, message);
+        group.throughput(Throughput::Bytes(body.len() as u64));
+        group.bench_function(*name, |b| {
+            b.iter(|| {
+                let body = body.clone();
+                let client = tower::service_fn(move |_: http::Request<Vec<u8>>| {
+                    let res = http::Response::new(hyper_pkg::Body::from(body.clone()));
+                    futures::future::ok::<_, hyper_pkg::Error>(res)
+                });
+                let token = twitter_stream::Token::new("ck", "cs", "ak", "as");
+                let stream = twitter_stream::Builder::new(token).listen_with_client(client);
+                block_on(async {
+                    stream
+                        .await
+                        .unwrap()
+                        .try_fold(0, |n, json| async move { Ok(n + json.len()) })
+                        .await
+                        .unwrap()
+                })
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, stream);
+criterion_main!(benches);
@@ -28,13 +28,11 @@ extern crate hyper_pkg as hyper;
 
 use std::fs::File;
 use std::path::PathBuf;
-use std::pin::Pin;
 
 use futures::prelude::*;
-use http::header::{HeaderValue, AUTHORIZATION, CONTENT_TYPE};
-use http_body::Body as _;
 use serde::de;
 use serde::Deserialize;
+use twitter_stream::rest;
 
 #[derive(Deserialize)]
 #[serde(untagged)]
@@ -67,38 +65,17 @@ struct User {
     screen_name: String,
 }
 
-/// Represents a GET statuses/update request.
+/// Represents a POST statuses/update request.
 #[derive(oauth::Authorize)]
-struct StatusUpdate<'a> {
-    status: &'a str,
+struct StatusUpdate {
+    status: String,
     in_reply_to_status_id: Option<u64>,
 }
 
-#[derive(Deserialize)]
-#[serde(remote = "twitter_stream::Token")]
-struct TokenDef {
-    // The `getter` attribute is required to make the `Deserialize` impl use the `From` conversion,
-    // even if we are not deriving `Serialize` here.
-    #[serde(getter = "__")]
-    consumer_key: String,
-    consumer_secret: String,
-    access_key: String,
-    access_secret: String,
-}
-
-impl From<TokenDef> for twitter_stream::Token {
-    fn from(def: TokenDef) -> twitter_stream::Token {
-        twitter_stream::Token::new(
-            def.consumer_key,
-            def.consumer_secret,
-            def.access_key,
-            def.access_secret,
-        )
-    }
-}
-
 type HttpsConnector = hyper_tls::HttpsConnector<hyper::client::HttpConnector>;
 
+const STATUSES_UPDATE: &str = "https://api.twitter.com/1.1/statuses/update.json";
+
 #[tokio::main]
 async fn main() {
     let mut credential_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
@@ -108,14 +85,10 @@ async fn main() {
     let mut client = hyper::Client::builder().build::<_, hyper::Body>(conn);
 
     let credential = File::open(credential_path).unwrap();
-    let token =
-        TokenDef::deserialize(&mut serde_json::Deserializer::from_reader(credential)).unwrap();
-
-    let mut oauth = oauth::Builder::new(token.client.as_ref(), oauth::HmacSha1);
-    oauth.token(token.token.as_ref());
+    let token: twitter_stream::Token = serde_json::from_reader(credential).unwrap();
 
     // Information of the authenticated user:
-    let user = verify_credentials(&oauth, &client).await;
+    let user = token.verify_with_client(client.clone()).await.unwrap();
 
     let mut stream = twitter_stream::Builder::new(token.as_ref())
         .track(format!("@{}", user.screen_name))
@@ -125,17 +98,31 @@ async fn main() {
     while let Some(json) = stream.next().await {
         if let Ok(StreamMessage::Tweet(tweet)) = serde_json::from_str(&json.unwrap()) {
             if !tweet.is_retweet
-                && tweet.user.id != user.id
+                && tweet.user.id != user.user_id
                 && tweet.entities.map_or(false, |e| {
-                    e.user_mentions.iter().any(|mention| mention.id == user.id)
+                    e.user_mentions
+                        .iter()
+                        .any(|mention| mention.id == user.user_id)
                 })
             {
                 // Send a reply
-                let tweeting = StatusUpdate {
-                    status: &format!("@{} {}", tweet.user.screen_name, tweet.text),
+                let update = StatusUpdate {
+                    status: format!("@{} {}", tweet.user.screen_name, tweet.text),
                     in_reply_to_status_id: Some(tweet.id),
-                }
-                .send(&oauth, &client);
+                };
+                let tweeting = rest::request_with_client(
+                    &http::Method::POST,
+                    &http::Uri::from_static(STATUSES_UPDATE),
+                    update,
+                    &token,
+                    client.clone(),
+                )
+                .map(|res| {
+                    let res = res.unwrap();
+                    if !res.status.is_success() {
+                        panic!("HTTP error: {}", res.status);
+                    }
+                });
                 tokio::spawn(tweeting);
             }
         }
@@ -178,63 +165,3 @@ impl<'de> Deserialize<'de> for Tweet {
         })
     }
 }
-
-impl<'a> StatusUpdate<'a> {
-    /// Performs the GET statuses/update request.
-    fn send(
-        &self,
-        oauth: &oauth::Builder<'_, oauth::HmacSha1, &str>,
-        client: &hyper::Client<HttpsConnector>,
-    ) -> impl Future<Output = ()> {
-        const URI: &str = "https://api.twitter.com/1.1/statuses/update.json";
-
-        let oauth::Request {
-            authorization,
-            data: form,
-        } = oauth.build("POST", URI, self);
-        let req = http::Request::post(http::Uri::from_static(URI))
-            .header(
-                CONTENT_TYPE,
-                HeaderValue::from_static("application/x-www-form-urlencoded"),
-            )
-            .header(AUTHORIZATION, authorization)
-            .body(form.into())
-            .unwrap();
-
-        parse_response::<de::IgnoredAny>(client.request(req)).map(|_| ())
-    }
-}
-
-/// Performs a GET account/verify_credentials request.
-fn verify_credentials(
-    oauth: &oauth::Builder<'_, oauth::HmacSha1, &str>,
-    client: &hyper::Client<HttpsConnector>,
-) -> impl Future<Output = User> {
-    const URI: &str = "https://api.twitter.com/1.1/account/verify_credentials.json";
-
-    let oauth::Request { authorization, .. } = oauth.build("GET", URI, ());
-    let req = http::Request::get(http::Uri::from_static(URI))
-        .header(AUTHORIZATION, authorization)
-        .body(Default::default())
-        .unwrap();
-
-    parse_response(client.request(req))
-}
-
-fn parse_response<T: de::DeserializeOwned>(
-    res: hyper::client::ResponseFuture,
-) -> impl Future<Output = T> {
-    res.then(|res| {
-        let mut res = res.unwrap();
-        if !res.status().is_success() {
-            panic!("HTTP error: {}", res.status());
-        }
-
-        stream::poll_fn(move |cx| Pin::new(&mut res).poll_data(cx))
-            .try_fold(Vec::new(), |mut vec, chunk| {
-                vec.extend_from_slice(&chunk);
-                async { Ok(vec) }
-            })
-            .map(|body| serde_json::from_slice(&body.unwrap()).unwrap())
-    })
-}
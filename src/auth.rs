@@ -0,0 +1,544 @@
+//! Helpers for the three-legged [OAuth 1.0a flow][flow] to obtain a [`Token`] of a user.
+//!
+//! [flow]: https://developer.twitter.com/en/docs/basics/authentication/oauth-1-0a/obtaining-user-access-tokens
+//!
+//! The flow consists of the following steps:
+//!
+//! 1. Obtain a set of temporary credentials with [`request_token`].
+//! 2. Let the user visit [`authorize_url`] (or [`authenticate_url`]) and authorize the app.
+//! 3. Exchange the temporary credentials and the verifier for a `Token` with [`access_token`].
+//!
+//! The verifier is either a PIN shown to the user (if the callback is `"oob"`) or
+//! the `oauth_verifier` query parameter of the request to the callback URL, which can be received
+//! with [`CallbackListener`].
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! use twitter_stream::{auth, Credentials};
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! let client = Credentials::new("consumer_key", "consumer_secret");
+//!
+//! let temporary = auth::request_token(client, "oob").await.unwrap();
+//! println!("Open {} and enter the PIN:", auth::authorize_url(&temporary));
+//!
+//! let mut pin = String::new();
+//! std::io::stdin().read_line(&mut pin).unwrap();
+//!
+//! let access = auth::access_token(client, temporary.as_ref(), pin.trim())
+//!     .await
+//!     .unwrap();
+//! println!("Authorized as @{}", access.screen_name);
+//! let token = access.token;
+//! # drop(token);
+//! # }
+//! ```
+
+#[cfg(feature = "hyper")]
+pub use self::callback::{Callback, CallbackListener};
+
+use std::borrow::Borrow;
+use std::error;
+use std::fmt::{self, Display, Formatter};
+use std::future::Future;
+
+use http::{Method, Uri};
+use http_body::Body;
+use oauth::Credentials;
+use percent_encoding::percent_decode_str;
+
+use crate::builder::{sign_request_with, SigningOptions};
+use crate::error::Error;
+use crate::service::HttpService;
+use crate::token::Token;
+use crate::util::collect_body;
+#[cfg(feature = "hyper")]
+use crate::util::https_client;
+
+const REQUEST_TOKEN: &str = "https://api.twitter.com/oauth/request_token";
+const AUTHORIZE: &str = "https://api.twitter.com/oauth/authorize";
+const AUTHENTICATE: &str = "https://api.twitter.com/oauth/authenticate";
+const ACCESS_TOKEN: &str = "https://api.twitter.com/oauth/access_token";
+
+/// The result of [`access_token`].
+#[derive(Clone, Debug)]
+pub struct AccessToken {
+    /// The token to act on behalf of the user.
+    pub token: Token,
+    /// ID of the user who authorized the app.
+    pub user_id: u64,
+    /// Screen name of the user who authorized the app.
+    pub screen_name: String,
+}
+
+/// An error occurred during the authorization flow.
+#[derive(Debug)]
+pub enum AuthError<E = Box<dyn error::Error + Send + Sync>> {
+    /// An error occurred while sending the request or receiving the response.
+    Request(Error<E>),
+    /// The response lacks the given field.
+    MissingField(&'static str),
+    /// The server did not confirm the callback URL.
+    CallbackNotConfirmed,
+}
+
+/// Obtains a set of temporary credentials (aka request token) with `POST oauth/request_token`.
+///
+/// `callback` is the URL the user is redirected to after authorizing the app,
+/// or `"oob"` to use the PIN-based flow.
+///
+/// # Panics
+///
+/// This will panic if the underlying HTTPS connector failed to initialize.
+#[cfg(feature = "hyper")]
+#[cfg_attr(docsrs, doc(cfg(feature = "hyper")))]
+pub fn request_token<C: Borrow<str>>(
+    client_credentials: Credentials<C>,
+    callback: &str,
+) -> impl Future<Output = Result<Credentials, AuthError<hyper_pkg::Error>>> {
+    request_token_with_client(client_credentials, callback, https_client())
+}
+
+/// Same as [`request_token`] except that it uses `client` to make HTTP request to the endpoint.
+///
+/// # Panics
+///
+/// This will call `<S as Service>::call` without checking for `<S as Service>::poll_ready`
+/// and may cause a panic if `client` is not ready to send an HTTP request yet.
+pub fn request_token_with_client<C, S, B>(
+    client_credentials: Credentials<C>,
+    callback: &str,
+    mut client: S,
+) -> impl Future<Output = Result<Credentials, AuthError<S::Error>>>
+where
+    C: Borrow<str>,
+    S: HttpService<B, Response = http::Response<<S as HttpService<B>>::ResponseBody>>,
+    <S::ResponseBody as Body>::Error: Into<S::Error>,
+    B: From<Vec<u8>>,
+{
+    let mut oauth = oauth::Builder::new(client_credentials.as_ref(), oauth::HmacSha1);
+    oauth.callback(callback);
+    let req = sign_request_with(
+        &Method::POST,
+        &Uri::from_static(REQUEST_TOKEN),
+        oauth,
+        (),
+        &SigningOptions::default(),
+    );
+    let res = client.call(req.map(Into::into));
+
+    async move {
+        let form = send(res).await?;
+        if form.get("oauth_callback_confirmed") != Some("true") {
+            return Err(AuthError::CallbackNotConfirmed);
+        }
+        Ok(Credentials::new(
+            form.take("oauth_token")?,
+            form.take("oauth_token_secret")?,
+        ))
+    }
+}
+
+/// Returns the URL of the `GET oauth/authorize` endpoint for the temporary credentials,
+/// where the user authorizes the app.
+pub fn authorize_url<T: Borrow<str>>(temporary: &Credentials<T>) -> String {
+    endpoint_url(AUTHORIZE, temporary.identifier())
+}
+
+/// Returns the URL of the `GET oauth/authenticate` endpoint for the temporary credentials.
+///
+/// Unlike [`authorize_url`], the user is redirected to the callback URL without being asked
+/// to authorize the app again if they have already done so ("Sign in with Twitter").
+pub fn authenticate_url<T: Borrow<str>>(temporary: &Credentials<T>) -> String {
+    endpoint_url(AUTHENTICATE, temporary.identifier())
+}
+
+/// Exchanges the temporary credentials and the verifier for a `Token`
+/// with `POST oauth/access_token`.
+///
+/// # Panics
+///
+/// This will panic if the underlying HTTPS connector failed to initialize.
+#[cfg(feature = "hyper")]
+#[cfg_attr(docsrs, doc(cfg(feature = "hyper")))]
+pub fn access_token<C: Borrow<str>>(
+    client_credentials: Credentials<C>,
+    temporary: Credentials<&str>,
+    verifier: &str,
+) -> impl Future<Output = Result<AccessToken, AuthError<hyper_pkg::Error>>> {
+    access_token_with_client(client_credentials, temporary, verifier, https_client())
+}
+
+/// Same as [`access_token`] except that it uses `client` to make HTTP request to the endpoint.
+///
+/// # Panics
+///
+/// This will call `<S as Service>::call` without checking for `<S as Service>::poll_ready`
+/// and may cause a panic if `client` is not ready to send an HTTP request yet.
+pub fn access_token_with_client<C, S, B>(
+    client_credentials: Credentials<C>,
+    temporary: Credentials<&str>,
+    verifier: &str,
+    mut client: S,
+) -> impl Future<Output = Result<AccessToken, AuthError<S::Error>>>
+where
+    C: Borrow<str>,
+    S: HttpService<B, Response = http::Response<<S as HttpService<B>>::ResponseBody>>,
+    <S::ResponseBody as Body>::Error: Into<S::Error>,
+    B: From<Vec<u8>>,
+{
+    let mut oauth = oauth::Builder::new(client_credentials.as_ref(), oauth::HmacSha1);
+    oauth.token(temporary).verifier(verifier);
+    let req = sign_request_with(
+        &Method::POST,
+        &Uri::from_static(ACCESS_TOKEN),
+        oauth,
+        (),
+        &SigningOptions::default(),
+    );
+    let res = client.call(req.map(Into::into));
+
+    let client_credentials = Credentials::new(
+        client_credentials.identifier().to_owned(),
+        client_credentials.secret().to_owned(),
+    );
+
+    async move {
+        let form = send(res).await?;
+        let token = Credentials::new(
+            form.take("oauth_token")?,
+            form.take("oauth_token_secret")?,
+        );
+        let user_id = form
+            .get("user_id")
+            .and_then(|id| id.parse().ok())
+            .ok_or(AuthError::MissingField("user_id"))?;
+        Ok(AccessToken {
+            token: Token::from_credentials(client_credentials, token),
+            user_id,
+            screen_name: form.take("screen_name")?,
+        })
+    }
+}
+
+impl<E: error::Error + 'static> error::Error for AuthError<E> {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            AuthError::Request(ref e) => Some(e),
+            AuthError::MissingField(_) | AuthError::CallbackNotConfirmed => None,
+        }
+    }
+}
+
+impl<E: Display> Display for AuthError<E> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match *self {
+            AuthError::Request(ref e) => Display::fmt(e, f),
+            AuthError::MissingField(field) => write!(f, "missing field `{}` in response", field),
+            AuthError::CallbackNotConfirmed => f.write_str("callback URL was not confirmed"),
+        }
+    }
+}
+
+/// An `application/x-www-form-urlencoded` response body.
+struct Form(Vec<(String, String)>);
+
+impl Form {
+    fn parse(input: &[u8]) -> Self {
+        let input = String::from_utf8_lossy(input);
+        Form(parse_query(&input))
+    }
+
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.iter().find(|(k, _)| k == key).map(|(_, v)| &**v)
+    }
+
+    fn take<E>(&self, key: &'static str) -> Result<String, AuthError<E>> {
+        self.get(key)
+            .map(str::to_owned)
+            .ok_or(AuthError::MissingField(key))
+    }
+}
+
+async fn send<F, R, E>(res: F) -> Result<Form, AuthError<E>>
+where
+    F: Future<Output = Result<http::Response<R>, E>>,
+    R: Body,
+    R::Error: Into<E>,
+{
+    let res = res.await.map_err(|e| AuthError::Request(Error::Service(e)))?;
+    if !res.status().is_success() {
+        return Err(AuthError::Request(Error::Http(res.status())));
+    }
+    let body = collect_body(res.into_body())
+        .await
+        .map_err(|e| AuthError::Request(e.map_service(Into::into)))?;
+    Ok(Form::parse(&body))
+}
+
+pub(crate) fn parse_query(input: &str) -> Vec<(String, String)> {
+    let decode = |s: &str| {
+        percent_decode_str(&s.replace('+', " "))
+            .decode_utf8_lossy()
+            .into_owned()
+    };
+    input
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let mut kv = pair.splitn(2, '=');
+            let k = kv.next().unwrap_or_default();
+            let v = kv.next().unwrap_or_default();
+            (decode(k), decode(v))
+        })
+        .collect()
+}
+
+fn endpoint_url(endpoint: &str, oauth_token: &str) -> String {
+    format!(
+        "{}?oauth_token={}",
+        endpoint,
+        percent_encoding::utf8_percent_encode(oauth_token, percent_encoding::NON_ALPHANUMERIC)
+    )
+}
+
+#[cfg(feature = "hyper")]
+mod callback {
+    use std::convert::Infallible;
+    use std::future::Future;
+    use std::net::SocketAddr;
+    use std::pin::Pin;
+    use std::sync::{Arc, Mutex};
+
+    use futures_channel::oneshot;
+    use hyper_pkg::server::conn::AddrStream;
+    use hyper_pkg::service::{make_service_fn, service_fn};
+    use hyper_pkg::{Body, Request, Response, Server};
+
+    const CALLBACK_PATH: &str = "/callback";
+
+    /// A tiny HTTP server that receives the redirect to the callback URL
+    /// after the user authorizes the app.
+    ///
+    /// This requires a Tokio runtime.
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// use twitter_stream::auth::{self, Callback, CallbackListener};
+    /// use twitter_stream::Credentials;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let client = Credentials::new("consumer_key", "consumer_secret");
+    ///
+    /// let listener = CallbackListener::bind(&([127, 0, 0, 1], 8080).into()).unwrap();
+    /// let temporary = auth::request_token(client, &listener.callback_url())
+    ///     .await
+    ///     .unwrap();
+    /// println!("Open {}", auth::authorize_url(&temporary));
+    ///
+    /// if let Callback::Authorized { oauth_verifier, .. } = listener.wait().await.unwrap() {
+    ///     let access = auth::access_token(client, temporary.as_ref(), &oauth_verifier)
+    ///         .await
+    ///         .unwrap();
+    /// # drop(access);
+    /// }
+    /// # }
+    /// ```
+    pub struct CallbackListener {
+        local_addr: SocketAddr,
+        server: Pin<Box<dyn Future<Output = hyper_pkg::Result<()>> + Send>>,
+        callback: Arc<Mutex<Option<Callback>>>,
+    }
+
+    /// A request to the callback URL received by [`CallbackListener`].
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    pub enum Callback {
+        /// The user authorized the app.
+        Authorized {
+            /// Identifier of the temporary credentials.
+            oauth_token: String,
+            /// The verifier to be passed to [`access_token`](super::access_token).
+            oauth_verifier: String,
+        },
+        /// The user denied the authorization.
+        Denied {
+            /// Identifier of the temporary credentials.
+            oauth_token: String,
+        },
+    }
+
+    impl CallbackListener {
+        /// Binds the listener to `addr`.
+        ///
+        /// Set the port of `addr` to `0` to let the OS choose an unused port.
+        pub fn bind(addr: &SocketAddr) -> hyper_pkg::Result<Self> {
+            let callback = Arc::new(Mutex::new(None));
+            let (tx, rx) = oneshot::channel::<()>();
+            let tx = Arc::new(Mutex::new(Some(tx)));
+
+            let make_service = {
+                let callback = callback.clone();
+                make_service_fn(move |_: &AddrStream| {
+                    let callback = callback.clone();
+                    let tx = tx.clone();
+                    async move {
+                        Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                            let response = handle(&req, &callback, &tx);
+                            async move { Ok::<_, Infallible>(response) }
+                        }))
+                    }
+                })
+            };
+
+            let server = Server::try_bind(addr)?.serve(make_service);
+            let local_addr = server.local_addr();
+            let server = server.with_graceful_shutdown(async {
+                let _ = rx.await;
+            });
+
+            Ok(CallbackListener {
+                local_addr,
+                server: Box::pin(server),
+                callback,
+            })
+        }
+
+        /// Returns the local address that the listener is bound to.
+        pub fn local_addr(&self) -> SocketAddr {
+            self.local_addr
+        }
+
+        /// Returns the callback URL to be passed to [`request_token`](super::request_token).
+        pub fn callback_url(&self) -> String {
+            format!("http://{}{}", self.local_addr, CALLBACK_PATH)
+        }
+
+        /// Waits for a request to the callback URL.
+        pub async fn wait(self) -> hyper_pkg::Result<Callback> {
+            self.server.await?;
+            let callback = self.callback.lock().unwrap().take();
+            Ok(callback.expect("server shut down without receiving a callback"))
+        }
+    }
+
+    fn handle(
+        req: &Request<Body>,
+        callback: &Mutex<Option<Callback>>,
+        shutdown: &Mutex<Option<oneshot::Sender<()>>>,
+    ) -> Response<Body> {
+        if req.uri().path() != CALLBACK_PATH {
+            return not_found();
+        }
+
+        let query = super::parse_query(req.uri().query().unwrap_or_default());
+        let get = |key: &str| {
+            query
+                .iter()
+                .find(|(k, _)| k == key)
+                .map(|(_, v)| v.clone())
+        };
+
+        let (received, message) = if let Some(oauth_token) = get("denied") {
+            (Callback::Denied { oauth_token }, "Authorization denied.")
+        } else if let (Some(oauth_token), Some(oauth_verifier)) =
+            (get("oauth_token"), get("oauth_verifier"))
+        {
+            let received = Callback::Authorized {
+                oauth_token,
+                oauth_verifier,
+            };
+            (received, "Authorized. You can close this window now.")
+        } else {
+            return not_found();
+        };
+
+        let mut callback = callback.lock().unwrap();
+        if callback.is_none() {
+            *callback = Some(received);
+            if let Some(tx) = shutdown.lock().unwrap().take() {
+                let _ = tx.send(());
+            }
+        }
+
+        Response::new(Body::from(message))
+    }
+
+    fn not_found() -> Response<Body> {
+        Response::builder()
+            .status(http::StatusCode::NOT_FOUND)
+            .body(Body::empty())
+            .unwrap()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn access_token() {
+        let client = tower::service_fn(|req: http::Request<Vec<u8>>| {
+            assert_eq!(req.uri(), ACCESS_TOKEN);
+            let authorization = req.headers()[http::header::AUTHORIZATION]
+                .to_str()
+                .unwrap();
+            assert!(authorization.contains(r#"oauth_token="temporary""#));
+            assert!(authorization.contains(r#"oauth_verifier="1234567""#));
+            let body = "oauth_token=6253282-eWudHldSbIaelX7swmsiHImEL4KinwaGloHANdrY\
+                        &oauth_token_secret=2EEfA6BG3ly3sR3RjE0IBSnlQu4ZrUzPiYKmrkVU\
+                        &user_id=6253282\
+                        &screen_name=twitterapi";
+            futures::future::ok::<_, hyper_pkg::Error>(http::Response::new(hyper_pkg::Body::from(
+                body,
+            )))
+        });
+
+        let client_credentials = Credentials::new("ck", "cs");
+        let temporary = Credentials::new("temporary", "secret");
+        let access = futures::executor::block_on(access_token_with_client(
+            client_credentials,
+            temporary,
+            "1234567",
+            client,
+        ))
+        .unwrap();
+
+        assert_eq!(access.user_id, 6253282);
+        assert_eq!(access.screen_name, "twitterapi");
+        assert_eq!(access.token.client.identifier, "ck");
+        assert_eq!(
+            access.token.token.identifier,
+            "6253282-eWudHldSbIaelX7swmsiHImEL4KinwaGloHANdrY"
+        );
+    }
+
+    #[cfg(feature = "hyper")]
+    #[tokio::test]
+    async fn callback_listener() {
+        let listener = CallbackListener::bind(&([127, 0, 0, 1], 0).into()).unwrap();
+        let query = "?oauth_token=temporary&oauth_verifier=1234567";
+        let other = format!("http://{}/other{}", listener.local_addr(), query);
+        let uri = format!("{}{}", listener.callback_url(), query);
+
+        let client = hyper_pkg::Client::new();
+        let (callback, res) = futures::join!(listener.wait(), async {
+            // Requests to other paths must not be taken as the callback.
+            let res = client.get(other.parse().unwrap()).await.unwrap();
+            assert_eq!(res.status(), http::StatusCode::NOT_FOUND);
+            client.get(uri.parse().unwrap()).await
+        });
+
+        assert_eq!(res.unwrap().status(), http::StatusCode::OK);
+        assert_eq!(
+            callback.unwrap(),
+            Callback::Authorized {
+                oauth_token: "temporary".to_owned(),
+                oauth_verifier: "1234567".to_owned(),
+            }
+        );
+    }
+}
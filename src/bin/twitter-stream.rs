@@ -0,0 +1,344 @@
+//! A command-line tool that dumps the Streaming API to stdout or files as NDJSON.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, LineWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::Duration;
+
+use futures_util::TryStreamExt;
+use structopt::StructOpt;
+use twitter_stream::builder::{BoundingBox, FilterLevel, RequestMethod, Uri};
+use twitter_stream::token::{LoadError, Secret};
+use twitter_stream::{Builder, Error, Token};
+
+#[derive(StructOpt)]
+#[structopt(name = "twitter-stream", about = "Dumps the Twitter Streaming API as NDJSON.")]
+struct Opt {
+    /// A comma-separated list of phrases to filter Tweets by.
+    #[structopt(long)]
+    track: Option<String>,
+    /// A comma-separated list of user IDs to receive Tweets from.
+    #[structopt(long, use_delimiter = true)]
+    follow: Vec<u64>,
+    /// A comma-separated list of bounding boxes, four numbers (west,south,east,north) per box.
+    #[structopt(long, use_delimiter = true, allow_hyphen_values = true)]
+    locations: Vec<f64>,
+    /// A comma-separated list of language identifiers.
+    #[structopt(long)]
+    language: Option<String>,
+    /// The minimum `filter_level` Tweet attribute to receive (`none`, `low` or `medium`).
+    #[structopt(long, parse(try_from_str = parse_filter_level))]
+    filter_level: Option<FilterLevel>,
+    /// Receive messages when in danger of being disconnected.
+    #[structopt(long)]
+    stall_warnings: bool,
+    /// The `count` parameter (requires elevated access).
+    #[structopt(long, allow_hyphen_values = true)]
+    count: Option<i32>,
+    /// Override the endpoint URI to be connected.
+    #[structopt(long)]
+    endpoint: Option<Uri>,
+    /// HTTP method to use with `--endpoint`.
+    /// Defaults to `POST` if any filter predicate is given and `GET` otherwise.
+    #[structopt(long, requires = "endpoint")]
+    method: Option<RequestMethod>,
+    /// Number of seconds to add to the local clock when signing requests.
+    #[structopt(long, allow_hyphen_values = true)]
+    timestamp_offset: Option<i64>,
+    /// Learn the clock skew from a `401 Unauthorized` response and retry with a corrected
+    /// timestamp.
+    #[structopt(long)]
+    adjust_clock_skew: bool,
+    /// Path to a JSON file containing `consumer_key`, `consumer_secret`, `access_key` and
+    /// `access_secret` (a TOML file is accepted as well if built with the `toml` feature).
+    /// If omitted, the credentials are read from the `TWITTER_CONSUMER_KEY`,
+    /// `TWITTER_CONSUMER_SECRET`, `TWITTER_ACCESS_KEY` and `TWITTER_ACCESS_SECRET`
+    /// environment variables.
+    #[structopt(long, parse(from_os_str))]
+    credentials: Option<PathBuf>,
+    /// Write messages to the file instead of stdout.
+    #[structopt(short, long, parse(from_os_str))]
+    output: Option<PathBuf>,
+    /// Start a new output file (`<output>.0`, `<output>.1`, ...) after this many bytes.
+    #[structopt(long, requires = "output")]
+    rotate_bytes: Option<u64>,
+    /// Exit instead of reconnecting when the connection is lost.
+    #[structopt(long)]
+    no_reconnect: bool,
+}
+
+/// Destination of the messages.
+enum Output {
+    Stdout(LineWriter<io::Stdout>),
+    File(RotatingFile),
+}
+
+/// A file that is switched to a new one after a certain number of bytes are written.
+struct RotatingFile {
+    path: PathBuf,
+    file: BufWriter<File>,
+    index: u32,
+    written: u64,
+    limit: Option<u64>,
+}
+
+enum RunError {
+    Stream(twitter_stream::hyper::Error),
+    Output(io::Error),
+}
+
+/// Reconnection strategy recommended by Twitter.
+///
+/// See <https://developer.twitter.com/en/docs/tweets/filter-realtime/guides/connecting>.
+#[derive(Default)]
+struct Backoff {
+    network: Option<Duration>,
+    http: Option<Duration>,
+}
+
+#[tokio::main]
+async fn main() {
+    let opt = Opt::from_args();
+
+    let token = match load_token(opt.credentials.as_deref()) {
+        Ok(token) => token,
+        Err(e) => {
+            eprintln!("error: failed to load credentials: {}", e);
+            process::exit(1);
+        }
+    };
+
+    let mut output = match Output::new(opt.output.clone(), opt.rotate_bytes) {
+        Ok(output) => output,
+        Err(e) => {
+            eprintln!("error: failed to open output: {}", e);
+            process::exit(1);
+        }
+    };
+
+    if opt.locations.len() % 4 != 0 {
+        eprintln!("error: the number of coordinates in `--locations` must be a multiple of 4");
+        process::exit(1);
+    }
+    let locations: Vec<BoundingBox> = opt
+        .locations
+        .chunks(4)
+        .map(|c| BoundingBox::new((c[0], c[1]), (c[2], c[3])))
+        .collect();
+
+    let mut builder = Builder::new(token);
+    builder
+        .stall_warnings(opt.stall_warnings)
+        .filter_level(opt.filter_level.clone())
+        .count(opt.count)
+        .timestamp_offset(opt.timestamp_offset.unwrap_or(0))
+        .adjust_clock_skew(opt.adjust_clock_skew);
+    if let Some(ref track) = opt.track {
+        builder.track(&track[..]);
+    }
+    builder.follow(&opt.follow[..]).locations(&locations[..]);
+    if let Some(ref language) = opt.language {
+        builder.language(&language[..]);
+    }
+    if let Some(ref endpoint) = opt.endpoint {
+        let method = opt.method.clone().unwrap_or_else(|| {
+            if opt.track.is_some() || !opt.follow.is_empty() || !locations.is_empty() {
+                RequestMethod::POST
+            } else {
+                RequestMethod::GET
+            }
+        });
+        builder.endpoint((method, endpoint.clone()));
+    }
+
+    let status = tokio::select! {
+        status = run(&builder, &mut output, !opt.no_reconnect) => status,
+        _ = tokio::signal::ctrl_c() => {
+            eprintln!("interrupted; shutting down");
+            0
+        }
+    };
+
+    if let Err(e) = output.flush() {
+        eprintln!("error: failed to flush output: {}", e);
+        process::exit(1);
+    }
+
+    process::exit(status);
+}
+
+/// Listens on the stream until an unrecoverable error occurs, returning the exit status.
+async fn run(
+    builder: &Builder<'_, Token<Secret, Secret>>,
+    output: &mut Output,
+    reconnect: bool,
+) -> i32 {
+    let mut backoff = Backoff::default();
+
+    loop {
+        let result = async {
+            let mut stream = builder.listen().await.map_err(RunError::Stream)?;
+            backoff.reset();
+            while let Some(json) = stream.try_next().await.map_err(RunError::Stream)? {
+                output.write_message(&json).map_err(RunError::Output)?;
+            }
+            Ok(())
+        }
+        .await;
+
+        let delay = match result {
+            Ok(()) => {
+                eprintln!("stream ended");
+                backoff.network()
+            }
+            Err(RunError::Stream(e)) => {
+                eprintln!("error: {}", e);
+                match e {
+                    Error::Http(status) if status.as_u16() == 420 => backoff.rate_limited(),
+                    Error::Http(status) if status.is_client_error() && status.as_u16() != 429 => {
+                        return 1;
+                    }
+                    Error::Http(_) => backoff.http(),
+                    _ => backoff.network(),
+                }
+            }
+            Err(RunError::Output(e)) => {
+                eprintln!("error: failed to write output: {}", e);
+                return 1;
+            }
+        };
+
+        if !reconnect {
+            return 1;
+        }
+
+        eprintln!("reconnecting in {:?}", delay);
+        tokio::time::delay_for(delay).await;
+    }
+}
+
+fn load_token(path: Option<&Path>) -> Result<Token<Secret, Secret>, LoadError> {
+    if let Some(path) = path {
+        Token::from_file(path)
+    } else {
+        Token::from_env()
+    }
+}
+
+impl Output {
+    fn new(path: Option<PathBuf>, limit: Option<u64>) -> io::Result<Self> {
+        if let Some(path) = path {
+            RotatingFile::new(path, limit).map(Output::File)
+        } else {
+            Ok(Output::Stdout(LineWriter::new(io::stdout())))
+        }
+    }
+
+    fn write_message(&mut self, json: &str) -> io::Result<()> {
+        match *self {
+            Output::Stdout(ref mut w) => writeln!(w, "{}", json),
+            Output::File(ref mut f) => f.write_message(json),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match *self {
+            Output::Stdout(ref mut w) => w.flush(),
+            Output::File(ref mut f) => f.file.flush(),
+        }
+    }
+}
+
+impl RotatingFile {
+    fn new(path: PathBuf, limit: Option<u64>) -> io::Result<Self> {
+        // Resume from the last file of a previous run, so that the runs are not interleaved.
+        let mut index = 0;
+        if limit.is_some() {
+            while rotated_path(&path, limit, index + 1).exists() {
+                index += 1;
+            }
+        }
+        let file = open_append(&rotated_path(&path, limit, index))?;
+        let written = file.metadata()?.len();
+        Ok(RotatingFile {
+            path,
+            file: BufWriter::new(file),
+            index,
+            written,
+            limit,
+        })
+    }
+
+    fn write_message(&mut self, json: &str) -> io::Result<()> {
+        let len = json.len() as u64 + 1;
+        if let Some(limit) = self.limit {
+            if self.written > 0 && self.written + len > limit {
+                self.file.flush()?;
+                self.index += 1;
+                let path = rotated_path(&self.path, self.limit, self.index);
+                self.file = BufWriter::new(open_append(&path)?);
+                self.written = 0;
+            }
+        }
+        writeln!(self.file, "{}", json)?;
+        self.written += len;
+        Ok(())
+    }
+}
+
+impl Backoff {
+    fn reset(&mut self) {
+        *self = Backoff::default();
+    }
+
+    /// Back off linearly for TCP/IP level network errors, starting at 250ms up to 16 seconds.
+    fn network(&mut self) -> Duration {
+        let next = self.network.map_or(Duration::from_millis(250), |d| {
+            (d + Duration::from_millis(250)).min(Duration::from_secs(16))
+        });
+        self.network = Some(next);
+        next
+    }
+
+    /// Back off exponentially for HTTP errors, starting at 5 seconds up to 320 seconds.
+    fn http(&mut self) -> Duration {
+        let next = self.http.map_or(Duration::from_secs(5), |d| {
+            (d * 2).min(Duration::from_secs(320))
+        });
+        self.http = Some(next);
+        next
+    }
+
+    /// Back off exponentially for HTTP 420 errors, starting at 1 minute.
+    fn rate_limited(&mut self) -> Duration {
+        let next = self.http.map_or(Duration::from_secs(60), |d| {
+            (d * 2).max(Duration::from_secs(60))
+        });
+        self.http = Some(next);
+        next
+    }
+}
+
+fn rotated_path(path: &Path, limit: Option<u64>, index: u32) -> PathBuf {
+    if limit.is_some() {
+        let mut name = path.as_os_str().to_owned();
+        name.push(format!(".{}", index));
+        name.into()
+    } else {
+        path.to_owned()
+    }
+}
+
+fn open_append(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+fn parse_filter_level(s: &str) -> Result<FilterLevel, String> {
+    match s {
+        "none" => Ok(FilterLevel::None),
+        "low" => Ok(FilterLevel::Low),
+        "medium" => Ok(FilterLevel::Medium),
+        _ => Err(format!("invalid filter level: {}", s)),
+    }
+}
@@ -0,0 +1,435 @@
+//! Fan-out of one stream to several consumers.
+//!
+//! Twitter limits the number of simultaneous connections of an account, so the consumers wanting
+//! the same stream should share one connection. [`TwitterStream::broadcast`] turns a stream into
+//! a [`Hub`], a `Future` that reads the stream and delivers each message to every [`Subscriber`].
+//!
+//! The messages are shared between the subscribers without being copied. Each subscriber has
+//! a bounded buffer of its own, and an [`Overflow`] policy deciding what to do when the buffer
+//! is full, so that a slow subscriber does not hold up the others nor the connection.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! use futures::prelude::*;
+//! use twitter_stream::broadcast::Overflow;
+//! use twitter_stream::Token;
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! let token = Token::new("consumer_key", "consumer_secret", "access_key", "access_secret");
+//!
+//! let hub = twitter_stream::Builder::sample(token)
+//!     .listen()
+//!     .await
+//!     .unwrap()
+//!     .broadcast();
+//! let archiver = hub.subscribe(1024, Overflow::DropOldest);
+//! let monitor = hub.subscribe(16, Overflow::DropNewest);
+//! tokio::spawn(hub);
+//!
+//! tokio::spawn(archiver.try_for_each(|json| {
+//!     println!("archive: {}", json);
+//!     future::ok(())
+//! }));
+//! monitor
+//!     .try_for_each(|json| {
+//!         println!("monitor: {}", json);
+//!         future::ok(())
+//!     })
+//!     .await
+//!     .unwrap();
+//! # }
+//! ```
+//!
+//! [`TwitterStream::broadcast`]: crate::TwitterStream::broadcast
+
+use std::collections::VecDeque;
+use std::error;
+use std::fmt::{self, Display, Formatter};
+use std::future::Future;
+use std::mem;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, Weak};
+use std::task::{Context, Poll, Waker};
+
+use bytes::Bytes;
+use futures_core::Stream;
+use futures_util::ready;
+use http_body::Body;
+use pin_project_lite::pin_project;
+
+use crate::error::Error;
+use crate::TwitterStream;
+
+pin_project! {
+    /// A `Future` delivering the messages of a stream to its [`Subscriber`]s, created by
+    /// [`TwitterStream::broadcast`](crate::TwitterStream::broadcast).
+    ///
+    /// The future resolves when the underlying stream ends or yields an error, after which
+    /// the subscribers end once they have yielded the buffered messages. If the stream yielded
+    /// an error, the subscribers yield a [`RecvError::Stream`] before ending. The messages
+    /// received while there is no subscriber are discarded.
+    pub struct Hub<B: Body> {
+        #[pin]
+        inner: TwitterStream<B>,
+        registry: Arc<Registry>,
+    }
+}
+
+/// A stream yielding the messages delivered by a [`Hub`].
+///
+/// Cloning a `Subscriber` creates a new subscriber of the same `Hub` with the same buffer
+/// capacity and `Overflow` policy, which receives the messages from the time it is created.
+pub struct Subscriber {
+    queue: Arc<Mutex<Queue>>,
+    registry: Weak<Registry>,
+}
+
+/// The policy for a [`Subscriber`] whose buffer is full.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Overflow {
+    /// Discard the oldest message in the buffer to make room for the new message.
+    DropOldest,
+    /// Discard the new message.
+    DropNewest,
+    /// Disconnect the subscriber, which yields a [`RecvError::Disconnected`] error after
+    /// yielding the buffered messages.
+    Disconnect,
+}
+
+/// An error yielded by a [`Subscriber`], after which the subscriber ends.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum RecvError {
+    /// The subscriber fell behind and was disconnected by the [`Overflow::Disconnect`] policy.
+    Disconnected,
+    /// The stream of the [`Hub`] yielded an error with the given message, ending the `Hub`.
+    Stream(String),
+}
+
+struct Registry {
+    // `None` if the hub has finished.
+    queues: Mutex<Option<Vec<Arc<Mutex<Queue>>>>>,
+}
+
+struct Queue {
+    buf: VecDeque<string::String<Bytes>>,
+    capacity: usize,
+    overflow: Overflow,
+    state: State,
+    dropped: u64,
+    waker: Option<Waker>,
+}
+
+#[derive(Clone, PartialEq, Eq)]
+enum State {
+    Open,
+    Disconnected,
+    // The hub has finished because of the error with the message.
+    Failed(String),
+    Closed,
+}
+
+impl<B: Body> Hub<B> {
+    pub(crate) fn new(inner: TwitterStream<B>) -> Self {
+        Hub {
+            inner,
+            registry: Arc::new(Registry {
+                queues: Mutex::new(Some(Vec::new())),
+            }),
+        }
+    }
+
+    /// Creates a new `Subscriber` with a buffer of `capacity` messages.
+    ///
+    /// # Panics
+    ///
+    /// This will panic if `capacity` is zero.
+    pub fn subscribe(&self, capacity: usize, overflow: Overflow) -> Subscriber {
+        assert!(capacity > 0, "capacity must be positive");
+        Subscriber::new(&self.registry, capacity, overflow)
+    }
+
+    /// Returns the number of the subscribers.
+    pub fn subscribers(&self) -> usize {
+        let mut queues = self.registry.queues.lock().unwrap();
+        queues.as_mut().map_or(0, |queues| {
+            prune(queues);
+            queues.len()
+        })
+    }
+}
+
+impl<B: Body> Future for Hub<B>
+where
+    B::Error: Display,
+{
+    type Output = Result<(), Error<B::Error>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut this = self.project();
+        loop {
+            let json = match ready!(this.inner.as_mut().poll_next(cx)) {
+                Some(Ok(json)) => json,
+                Some(Err(e)) => {
+                    this.registry.close(Some(&e.to_string()));
+                    return Poll::Ready(Err(e));
+                }
+                None => {
+                    this.registry.close(None);
+                    return Poll::Ready(Ok(()));
+                }
+            };
+
+            let mut queues = this.registry.queues.lock().unwrap();
+            if let Some(ref mut queues) = *queues {
+                prune(queues);
+                queues.retain(|queue| queue.lock().unwrap().push(&json));
+            }
+        }
+    }
+}
+
+impl<B: Body> fmt::Debug for Hub<B> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Hub")
+            .field("subscribers", &self.subscribers())
+            .finish()
+    }
+}
+
+impl Subscriber {
+    fn new(registry: &Arc<Registry>, capacity: usize, overflow: Overflow) -> Self {
+        let mut queue = Queue {
+            buf: VecDeque::with_capacity(capacity),
+            capacity,
+            overflow,
+            state: State::Open,
+            dropped: 0,
+            waker: None,
+        };
+        let mut queues = registry.queues.lock().unwrap();
+        let queue = if let Some(ref mut queues) = *queues {
+            let queue = Arc::new(Mutex::new(queue));
+            queues.push(queue.clone());
+            queue
+        } else {
+            queue.state = State::Closed;
+            Arc::new(Mutex::new(queue))
+        };
+
+        Subscriber {
+            queue,
+            registry: Arc::downgrade(registry),
+        }
+    }
+
+    /// Returns the number of the messages discarded because the buffer was full.
+    pub fn dropped(&self) -> u64 {
+        self.queue.lock().unwrap().dropped
+    }
+
+    /// Returns the number of the buffered messages.
+    pub fn len(&self) -> usize {
+        self.queue.lock().unwrap().buf.len()
+    }
+
+    /// Returns `true` if there is no buffered message.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Stream for Subscriber {
+    type Item = Result<string::String<Bytes>, RecvError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut queue = self.queue.lock().unwrap();
+
+        if let Some(json) = queue.buf.pop_front() {
+            return Poll::Ready(Some(Ok(json)));
+        }
+
+        match mem::replace(&mut queue.state, State::Closed) {
+            State::Open => {
+                queue.state = State::Open;
+                queue.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+            State::Disconnected => Poll::Ready(Some(Err(RecvError::Disconnected))),
+            State::Failed(message) => Poll::Ready(Some(Err(RecvError::Stream(message)))),
+            State::Closed => Poll::Ready(None),
+        }
+    }
+}
+
+impl Clone for Subscriber {
+    fn clone(&self) -> Self {
+        let (capacity, overflow) = {
+            let queue = self.queue.lock().unwrap();
+            (queue.capacity, queue.overflow)
+        };
+        match self.registry.upgrade() {
+            Some(registry) => Subscriber::new(&registry, capacity, overflow),
+            None => {
+                let registry = Arc::new(Registry {
+                    queues: Mutex::new(None),
+                });
+                Subscriber::new(&registry, capacity, overflow)
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Subscriber {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let queue = self.queue.lock().unwrap();
+        f.debug_struct("Subscriber")
+            .field("len", &queue.buf.len())
+            .field("capacity", &queue.capacity)
+            .field("overflow", &queue.overflow)
+            .field("dropped", &queue.dropped)
+            .finish()
+    }
+}
+
+impl error::Error for RecvError {}
+
+impl Display for RecvError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match *self {
+            RecvError::Disconnected => {
+                f.write_str("the subscriber fell behind and was disconnected")
+            }
+            RecvError::Stream(ref message) => write!(f, "the stream failed: {}", message),
+        }
+    }
+}
+
+impl Registry {
+    /// Closes the queues, with the message of the error that ended the hub if any.
+    fn close(&self, error: Option<&str>) {
+        if let Some(queues) = self.queues.lock().unwrap().take() {
+            for queue in queues {
+                queue.lock().unwrap().close(error);
+            }
+        }
+    }
+}
+
+impl Drop for Registry {
+    fn drop(&mut self) {
+        self.close(None);
+    }
+}
+
+impl Queue {
+    /// Pushes `json` to the queue, returning `false` if the subscriber has been disconnected.
+    fn push(&mut self, json: &string::String<Bytes>) -> bool {
+        if self.buf.len() == self.capacity {
+            self.dropped += 1;
+            match self.overflow {
+                Overflow::DropOldest => {
+                    self.buf.pop_front();
+                }
+                Overflow::DropNewest => return true,
+                Overflow::Disconnect => {
+                    self.state = State::Disconnected;
+                    self.wake();
+                    return false;
+                }
+            }
+        }
+        self.buf.push_back(json.clone());
+        self.wake();
+        true
+    }
+
+    fn close(&mut self, error: Option<&str>) {
+        if self.state == State::Open {
+            self.state = match error {
+                Some(message) => State::Failed(message.to_owned()),
+                None => State::Closed,
+            };
+        }
+        self.wake();
+    }
+
+    fn wake(&mut self) {
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// Removes the queues of the dropped subscribers.
+fn prune(queues: &mut Vec<Arc<Mutex<Queue>>>) {
+    queues.retain(|queue| Arc::strong_count(queue) > 1);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use futures::executor::block_on;
+    use futures::TryStreamExt;
+
+    use crate::util::test::stream_from_body;
+
+    fn hub() -> Hub<hyper_pkg::Body> {
+        stream_from_body(b"{\"n\":0}\r\n\r\n{\"n\":1}\r\n{\"n\":2}\r\n").broadcast()
+    }
+
+    fn collect(subscriber: Subscriber) -> Vec<Result<String, RecvError>> {
+        block_on(futures::StreamExt::collect::<Vec<_>>(
+            subscriber.map_ok(|json| json.to_string()),
+        ))
+    }
+
+    #[test]
+    fn overflow() {
+        let hub = hub();
+        let all = hub.subscribe(8, Overflow::DropNewest);
+        let clone = all.clone();
+        let oldest = hub.subscribe(2, Overflow::DropOldest);
+        let newest = hub.subscribe(2, Overflow::DropNewest);
+        let disconnect = hub.subscribe(2, Overflow::Disconnect);
+        drop(hub.subscribe(1, Overflow::Disconnect));
+        assert_eq!(hub.subscribers(), 5);
+
+        block_on(hub).unwrap();
+
+        // Subscribing after the hub has finished.
+        let late = oldest.clone();
+
+        assert_eq!(oldest.dropped(), 1);
+        assert_eq!(newest.dropped(), 1);
+        assert_eq!(disconnect.dropped(), 1);
+        let ok = |n: u32| Ok(format!("{{\"n\":{}}}", n));
+        assert_eq!(collect(all), [ok(0), ok(1), ok(2)]);
+        assert_eq!(collect(clone), [ok(0), ok(1), ok(2)]);
+        assert_eq!(collect(oldest), [ok(1), ok(2)]);
+        assert_eq!(collect(newest), [ok(0), ok(1)]);
+        assert_eq!(
+            collect(disconnect),
+            [ok(0), ok(1), Err(RecvError::Disconnected)]
+        );
+        assert_eq!(collect(late), []);
+    }
+
+    #[test]
+    fn stream_error() {
+        let hub = stream_from_body(b"{\"n\":0}\r\n{\"n\":\"\xFF\"}\r\n{\"n\":2}\r\n").broadcast();
+        let subscriber = hub.subscribe(8, Overflow::DropNewest);
+        let disconnect = hub.subscribe(1, Overflow::Disconnect);
+
+        let e = block_on(hub).unwrap_err();
+
+        let expected = [
+            Ok("{\"n\":0}".to_owned()),
+            Err(RecvError::Stream(e.to_string())),
+        ];
+        assert_eq!(collect(subscriber), expected);
+        assert_eq!(collect(disconnect), expected);
+    }
+}
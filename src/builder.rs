@@ -44,10 +44,13 @@ pub use http::Uri;
 
 use std::borrow::{Borrow, Cow};
 use std::fmt::{self, Formatter};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use http::header::{HeaderValue, AUTHORIZATION, CONTENT_LENGTH, CONTENT_TYPE};
 use http::Request;
 
+use crate::read_ahead::FutureReadAhead;
 use crate::service::HttpService;
 use crate::token::Token;
 use crate::util::fmt_join;
@@ -56,11 +59,23 @@ use crate::FutureTwitterStream;
 /// A builder for [`TwitterStream`](crate::TwitterStream).
 ///
 /// See the [`builder`][crate::builder] module documentation for details.
-#[derive(Clone, Debug)]
+///
+/// The `Debug` representation of `Builder` omits the token.
+#[derive(Clone)]
 pub struct Builder<'a, T = Token> {
     token: T,
     endpoint: Option<(RequestMethod, Uri)>,
     parameters: Parameters<'a>,
+    signing: SigningOptions,
+    adjust_clock_skew: bool,
+}
+
+/// Options to override the `oauth_timestamp` and `oauth_nonce` values of signed requests.
+#[derive(Clone, Default)]
+pub(crate) struct SigningOptions {
+    pub timestamp_offset: i64,
+    pub timestamp: Option<Arc<dyn Fn() -> u64 + Send + Sync>>,
+    pub nonce: Option<Arc<dyn Fn() -> String + Send + Sync>>,
 }
 
 /// Parameters to the Streaming API.
@@ -126,6 +141,8 @@ where
             token,
             endpoint: None,
             parameters: Parameters::default(),
+            signing: SigningOptions::default(),
+            adjust_clock_skew: false,
         }
     }
 
@@ -162,8 +179,27 @@ where
     #[cfg(feature = "hyper")]
     #[cfg_attr(docsrs, doc(cfg(feature = "hyper")))]
     pub fn listen(&self) -> crate::hyper::FutureTwitterStream {
-        let conn = hyper_tls::HttpsConnector::new();
-        self.listen_with_client(hyper_pkg::Client::builder().build::<_, hyper_pkg::Body>(conn))
+        let client = crate::util::https_client();
+        let mut ret = self.listen_with_client(client.clone());
+
+        if self.adjust_clock_skew {
+            let endpoint = self.endpoint.clone();
+            let token = Token::new(
+                self.token.client.identifier.borrow().to_owned(),
+                self.token.client.secret.borrow().to_owned(),
+                self.token.token.identifier.borrow().to_owned(),
+                self.token.token.secret.borrow().to_owned(),
+            );
+            let parameters = self.parameters.clone().into_owned();
+            let mut signing = self.signing.clone();
+            ret.retry = Some(Box::new(move |clock_skew| {
+                signing.timestamp_offset = clock_skew;
+                let req = prepare_request(endpoint.as_ref(), token.as_ref(), &parameters, &signing);
+                client.request(req.map(Into::into))
+            }));
+        }
+
+        ret
     }
 
     /// Same as [`listen`](Builder::listen) except that it uses `client` to make HTTP request
@@ -200,10 +236,68 @@ where
             self.endpoint.as_ref(),
             self.token.as_ref(),
             &self.parameters,
+            &self.signing,
         );
         let response = client.call(req.map(Into::into));
 
-        FutureTwitterStream { response }
+        FutureTwitterStream {
+            response,
+            retry: None,
+        }
+    }
+
+    /// Same as [`listen`](Builder::listen) except that the returned `Future` resolves to
+    /// a [`ReadAhead`](crate::read_ahead::ReadAhead) stream reading the response ahead into
+    /// a queue of `capacity` messages, along with its [`Driver`](crate::read_ahead::Driver).
+    ///
+    /// The [`stall_warnings`](Builder::stall_warnings) parameter is enabled regardless of
+    /// the setting of `self`.
+    ///
+    /// # Panics
+    ///
+    /// This will panic if the underlying HTTPS connector failed to initialize, or if `capacity`
+    /// is zero.
+    #[cfg(feature = "hyper")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "hyper")))]
+    pub fn listen_read_ahead(&self, capacity: usize) -> crate::hyper::FutureReadAhead {
+        FutureReadAhead::new(self.with_stall_warnings().listen(), capacity)
+    }
+
+    /// Same as [`listen_read_ahead`](Builder::listen_read_ahead) except that it uses `client`
+    /// to make HTTP request to the endpoint.
+    ///
+    /// # Panics
+    ///
+    /// This will panic if `capacity` is zero.
+    ///
+    /// This will call `<S as Service>::call` without checking for `<S as Service>::poll_ready`
+    /// and may cause a panic if `client` is not ready to send an HTTP request yet.
+    pub fn listen_read_ahead_with_client<S, B>(
+        &self,
+        capacity: usize,
+        client: S,
+    ) -> FutureReadAhead<S::Future>
+    where
+        S: HttpService<B>,
+        B: From<Vec<u8>>,
+    {
+        FutureReadAhead::new(
+            self.with_stall_warnings().listen_with_client(client),
+            capacity,
+        )
+    }
+
+    fn with_stall_warnings(&self) -> Builder<'a, Token<&str, &str>> {
+        Builder {
+            token: self.token.as_ref(),
+            endpoint: self.endpoint.clone(),
+            parameters: Parameters {
+                stall_warnings: true,
+                ..self.parameters.clone()
+            },
+            signing: self.signing.clone(),
+            adjust_clock_skew: self.adjust_clock_skew,
+        }
     }
 }
 
@@ -216,6 +310,50 @@ impl<'a, C, A> Builder<'a, Token<C, A>> {
         self
     }
 
+    /// Set the number of seconds to add to the local clock when signing requests.
+    ///
+    /// This is useful when the local clock is known to be off, in which case Twitter would
+    /// reject the requests with `401 Unauthorized`. The offset can be measured with
+    /// [`Token::verify_with_client`](crate::Token::verify_with_client).
+    pub fn timestamp_offset(&mut self, offset: i64) -> &mut Self {
+        self.signing.timestamp_offset = offset;
+        self
+    }
+
+    /// Set whether to learn the clock skew from the `Date` header of a `401 Unauthorized`
+    /// response and retry once with a corrected timestamp.
+    ///
+    /// The retry is only performed by [`listen`](Builder::listen), which owns its HTTP client.
+    /// The default is `false`.
+    pub fn adjust_clock_skew(&mut self, adjust_clock_skew: bool) -> &mut Self {
+        self.adjust_clock_skew = adjust_clock_skew;
+        self
+    }
+
+    /// Set a function returning the `oauth_timestamp` value to sign requests with,
+    /// instead of the local clock.
+    ///
+    /// This is intended for making signed requests deterministic in tests.
+    pub fn timestamp_source<F>(&mut self, source: F) -> &mut Self
+    where
+        F: Fn() -> u64 + Send + Sync + 'static,
+    {
+        self.signing.timestamp = Some(Arc::new(source));
+        self
+    }
+
+    /// Set a function returning the `oauth_nonce` value to sign requests with,
+    /// instead of a random string.
+    ///
+    /// This is intended for making signed requests deterministic in tests.
+    pub fn nonce_source<F>(&mut self, source: F) -> &mut Self
+    where
+        F: Fn() -> String + Send + Sync + 'static,
+    {
+        self.signing.nonce = Some(Arc::new(source));
+        self
+    }
+
     /// Reset the token to be used to log into Twitter.
     pub fn token(&mut self, token: Token<C, A>) -> &mut Self {
         self.token = token;
@@ -305,6 +443,55 @@ impl<'a, C, A> Builder<'a, Token<C, A>> {
     }
 }
 
+impl<'a, T> fmt::Debug for Builder<'a, T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Builder")
+            .field("token", &format_args!("<hidden>"))
+            .field("endpoint", &self.endpoint)
+            .field("parameters", &self.parameters)
+            .field("timestamp_offset", &self.signing.timestamp_offset)
+            .field("adjust_clock_skew", &self.adjust_clock_skew)
+            .finish()
+    }
+}
+
+impl<'a> Parameters<'a> {
+    #[cfg(feature = "hyper")]
+    fn into_owned(self) -> Parameters<'static> {
+        Parameters {
+            stall_warnings: self.stall_warnings,
+            filter_level: self.filter_level,
+            language: Cow::Owned(self.language.into_owned()),
+            follow: Cow::Owned(self.follow.into_owned()),
+            track: Cow::Owned(self.track.into_owned()),
+            locations: Cow::Owned(self.locations.into_owned()),
+            count: self.count,
+        }
+    }
+}
+
+impl SigningOptions {
+    fn timestamp(&self) -> Option<u64> {
+        if self.timestamp.is_none() && self.timestamp_offset == 0 {
+            return None;
+        }
+        let now = self.timestamp.as_ref().map_or_else(
+            || {
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|d| d.as_secs())
+                    .unwrap_or(0)
+            },
+            |f| f(),
+        );
+        Some(if self.timestamp_offset < 0 {
+            now.saturating_sub(self.timestamp_offset.wrapping_neg() as u64)
+        } else {
+            now.saturating_add(self.timestamp_offset as u64)
+        })
+    }
+}
+
 impl BoundingBox {
     /// Creates a `BoundingBox` with two `(longitude, latitude)` pairs.
     ///
@@ -377,6 +564,7 @@ fn prepare_request(
     endpoint: Option<&(RequestMethod, Uri)>,
     token: Token<&str, &str>,
     parameters: &Parameters<'_>,
+    signing: &SigningOptions,
 ) -> http::Request<Vec<u8>> {
     let uri;
     let (method, endpoint) = if let Some(&(ref method, ref endpoint)) = endpoint {
@@ -392,16 +580,51 @@ fn prepare_request(
         (&RequestMethod::POST, &uri)
     };
 
-    let req = Request::builder().method(method.clone());
+    #[allow(unused_mut)]
+    let mut req = sign_request(method, endpoint, token, parameters, signing);
 
     #[cfg(feature = "gzip")]
-    let req = req.header(
+    req.headers_mut().insert(
         http::header::ACCEPT_ENCODING,
         HeaderValue::from_static("gzip"),
     );
 
-    let mut oauth = oauth::Builder::new(token.client.as_ref(), oauth::HmacSha1);
-    oauth.token(token.token.as_ref());
+    req
+}
+
+/// Creates a request to `endpoint` signed with `token`.
+///
+/// `parameters` are encoded in the request body if `method` is `POST`,
+/// and in the query string otherwise.
+pub(crate) fn sign_request<A: oauth::Authorize>(
+    method: &RequestMethod,
+    endpoint: &Uri,
+    token: Token<&str, &str>,
+    parameters: A,
+    signing: &SigningOptions,
+) -> http::Request<Vec<u8>> {
+    let mut oauth = oauth::Builder::new(token.client, oauth::HmacSha1);
+    oauth.token(token.token);
+    sign_request_with(method, endpoint, oauth, parameters, signing)
+}
+
+/// Same as `sign_request` except that it takes a preconfigured `oauth::Builder`,
+/// allowing to sign requests with other combinations of credentials and OAuth parameters.
+pub(crate) fn sign_request_with<A: oauth::Authorize>(
+    method: &RequestMethod,
+    endpoint: &Uri,
+    oauth: oauth::Builder<'_, oauth::HmacSha1, &str>,
+    parameters: A,
+    signing: &SigningOptions,
+) -> http::Request<Vec<u8>> {
+    let req = Request::builder().method(method.clone());
+
+    let nonce = signing.nonce.as_ref().map(|f| f());
+    // Shorten the lifetime of `oauth` to that of `nonce`.
+    let mut oauth: oauth::Builder<'_, _, _> = oauth;
+    oauth
+        .nonce(nonce.as_deref())
+        .timestamp(signing.timestamp());
 
     if RequestMethod::POST == method {
         let oauth::Request {
@@ -462,3 +685,38 @@ fn fmt_locations(locs: &[BoundingBox], f: &mut Formatter<'_>) -> fmt::Result {
 fn not(p: &bool) -> bool {
     !p
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn deterministic_signing() {
+        let token = Token::new("ck", "cs", "ak", "as");
+        let mut builder = Builder::new(token);
+        builder
+            .track("@Twitter")
+            .timestamp_source(|| 1_000_000_000)
+            .nonce_source(|| "nonce".to_owned());
+
+        let sign = |builder: &Builder<'_, Token<&str, &str>>| {
+            prepare_request(
+                builder.endpoint.as_ref(),
+                builder.token.as_ref(),
+                &builder.parameters,
+                &builder.signing,
+            )
+        };
+
+        let req = sign(&builder);
+        let authorization = req.headers()[AUTHORIZATION].to_str().unwrap().to_owned();
+        assert!(authorization.contains(r#"oauth_nonce="nonce""#));
+        assert!(authorization.contains(r#"oauth_timestamp="1000000000""#));
+        assert_eq!(sign(&builder).headers()[AUTHORIZATION], authorization[..]);
+
+        builder.timestamp_offset(-100);
+        let req = sign(&builder);
+        let authorization = req.headers()[AUTHORIZATION].to_str().unwrap();
+        assert!(authorization.contains(r#"oauth_timestamp="999999900""#));
+    }
+}
@@ -0,0 +1,357 @@
+//! Codecs for the framing of the Streaming API.
+//!
+//! The messages of the Streaming API are delimited by CRLF (`\r\n`), or, when the `delimited`
+//! parameter is set to `length`, prefixed with their lengths in bytes. In both cases, the server
+//! sends blank lines as keep-alive signals between the messages.
+//!
+//! [`CrlfCodec`] and [`DelimitedCodec`] implement these framings as
+//! [`Decoder`](tokio_util::codec::Decoder)s and [`Encoder`](tokio_util::codec::Encoder)s, so that
+//! you can use them with `FramedRead` and the like, e.g. to read a stream relayed over
+//! a raw TCP socket.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! # #[cfg(feature = "codec")]
+//! # async fn doc() {
+//! use std::net::Ipv4Addr;
+//!
+//! use futures::prelude::*;
+//! use tokio::net::TcpStream;
+//! use tokio_util::codec::FramedRead;
+//! use twitter_stream::codec::CrlfCodec;
+//!
+//! let socket = TcpStream::connect((Ipv4Addr::LOCALHOST, 8080)).await.unwrap();
+//! FramedRead::new(socket, CrlfCodec::new().skip_blank_lines(true))
+//!     .try_for_each(|message| {
+//!         println!("{:?}", message);
+//!         future::ok(())
+//!     })
+//!     .await
+//!     .unwrap();
+//! # }
+//! ```
+
+use std::io;
+use std::str;
+
+use bytes::{Bytes, BytesMut};
+
+use crate::validate;
+
+/// A codec for the messages delimited by CRLF.
+///
+/// The decoder yields the lines without the CRLF, and the encoder appends a CRLF to
+/// the messages.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CrlfCodec {
+    skip_blank_lines: bool,
+    // The index in the buffer to resume searching for a CRLF from.
+    next_index: usize,
+}
+
+/// A codec for the messages prefixed with their lengths (`delimited=length`).
+///
+/// Each message is preceded by a line containing the length in bytes of the message,
+/// which includes the CRLF terminating the message. The decoder yields the messages without
+/// the terminating CRLF, and the encoder adds the length and the CRLF to the messages.
+#[derive(Clone, Copy, Debug)]
+pub struct DelimitedCodec {
+    skip_blank_lines: bool,
+    max_length: usize,
+    lines: CrlfCodec,
+    // The length of the message being read, if its length line has been read.
+    length: Option<usize>,
+}
+
+impl CrlfCodec {
+    /// Creates a new `CrlfCodec`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set whether the decoder should discard the blank lines (the keep-alive signals).
+    ///
+    /// Defaults to `false`, in which case the blank lines are yielded as is.
+    pub fn skip_blank_lines(mut self, skip_blank_lines: bool) -> Self {
+        self.skip_blank_lines = skip_blank_lines;
+        self
+    }
+
+    /// Decodes a line from `buf`, if `buf` contains a CRLF.
+    pub(crate) fn decode_line(&mut self, buf: &mut BytesMut) -> Option<Bytes> {
+        loop {
+            let line = self.next_line(buf)?;
+            if !(self.skip_blank_lines && validate::is_blank(&line)) {
+                return Some(line);
+            }
+        }
+    }
+
+    /// Decodes a line from `buf`, taking the remaining bytes as a line if `buf` does not contain
+    /// a CRLF.
+    pub(crate) fn decode_line_eof(&mut self, buf: &mut BytesMut) -> Option<Bytes> {
+        if let Some(line) = self.decode_line(buf) {
+            return Some(line);
+        }
+        self.next_index = 0;
+        let line = buf.split().freeze();
+        if line.is_empty() || self.skip_blank_lines && validate::is_blank(&line) {
+            None
+        } else {
+            Some(line)
+        }
+    }
+
+    fn next_line(&mut self, buf: &mut BytesMut) -> Option<Bytes> {
+        loop {
+            let i = match memchr::memchr(b'\n', &buf[self.next_index..]) {
+                Some(i) => self.next_index + i,
+                None => {
+                    self.next_index = buf.len();
+                    return None;
+                }
+            };
+            if i > 0 && buf[i - 1] == b'\r' {
+                let mut line = buf.split_to(i + 1);
+                line.truncate(i - 1); // Drop the CRLF
+                self.next_index = 0;
+                return Some(line.freeze());
+            }
+            self.next_index = i + 1;
+        }
+    }
+}
+
+/// The default value of [`DelimitedCodec::max_length`].
+const DEFAULT_MAX_LENGTH: usize = 8 * 1024 * 1024;
+
+impl DelimitedCodec {
+    /// Creates a new `DelimitedCodec`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set whether the decoder should discard the blank lines (the keep-alive signals).
+    ///
+    /// Defaults to `false`, in which case the blank lines are yielded as empty messages.
+    pub fn skip_blank_lines(mut self, skip_blank_lines: bool) -> Self {
+        self.skip_blank_lines = skip_blank_lines;
+        self
+    }
+
+    /// Set the maximum length in bytes of a message, including its terminating CRLF.
+    ///
+    /// The decoder fails with an [`InvalidData`](io::ErrorKind::InvalidData) error on a length
+    /// prefix exceeding the maximum, instead of buffering the message.
+    ///
+    /// Defaults to 8 MiB.
+    pub fn max_length(mut self, max_length: usize) -> Self {
+        self.max_length = max_length;
+        self
+    }
+
+    fn decode_message(&mut self, buf: &mut BytesMut) -> io::Result<Option<Bytes>> {
+        loop {
+            let length = match self.length {
+                Some(length) => length,
+                None => {
+                    let line = match self.lines.decode_line(buf) {
+                        Some(line) => line,
+                        None => return Ok(None),
+                    };
+                    if validate::is_blank(&line) {
+                        if self.skip_blank_lines {
+                            continue;
+                        }
+                        return Ok(Some(Bytes::new()));
+                    }
+                    let length = parse_length(&line)?;
+                    if length > self.max_length {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            "message length exceeds the maximum",
+                        ));
+                    }
+                    self.length = Some(length);
+                    length
+                }
+            };
+
+            if buf.len() < length {
+                buf.reserve(length - buf.len());
+                return Ok(None);
+            }
+
+            self.length = None;
+            let mut message = buf.split_to(length);
+            if message.ends_with(b"\r\n") {
+                message.truncate(length - 2);
+            }
+            return Ok(Some(message.freeze()));
+        }
+    }
+}
+
+impl Default for DelimitedCodec {
+    fn default() -> Self {
+        DelimitedCodec {
+            skip_blank_lines: false,
+            max_length: DEFAULT_MAX_LENGTH,
+            lines: CrlfCodec::default(),
+            length: None,
+        }
+    }
+}
+
+fn parse_length(line: &[u8]) -> io::Result<usize> {
+    str::from_utf8(line)
+        .ok()
+        .and_then(|line| line.trim().parse().ok())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "invalid message length"))
+}
+
+#[cfg(feature = "codec")]
+mod imp {
+    use super::*;
+
+    use bytes::{Buf, BufMut};
+    use tokio_util::codec::{Decoder, Encoder};
+
+    impl Decoder for CrlfCodec {
+        type Item = Bytes;
+        type Error = io::Error;
+
+        fn decode(&mut self, buf: &mut BytesMut) -> io::Result<Option<Bytes>> {
+            Ok(self.decode_line(buf))
+        }
+
+        fn decode_eof(&mut self, buf: &mut BytesMut) -> io::Result<Option<Bytes>> {
+            Ok(self.decode_line_eof(buf))
+        }
+    }
+
+    impl<T: Buf> Encoder<T> for CrlfCodec {
+        type Error = io::Error;
+
+        fn encode(&mut self, message: T, dst: &mut BytesMut) -> io::Result<()> {
+            dst.reserve(message.remaining() + 2);
+            dst.put(message);
+            dst.put_slice(b"\r\n");
+            Ok(())
+        }
+    }
+
+    impl Decoder for DelimitedCodec {
+        type Item = Bytes;
+        type Error = io::Error;
+
+        fn decode(&mut self, buf: &mut BytesMut) -> io::Result<Option<Bytes>> {
+            self.decode_message(buf)
+        }
+
+        fn decode_eof(&mut self, buf: &mut BytesMut) -> io::Result<Option<Bytes>> {
+            match self.decode_message(buf)? {
+                Some(message) => Ok(Some(message)),
+                None if buf.is_empty() && self.length.is_none() => Ok(None),
+                None => Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "stream ended in the middle of a message",
+                )),
+            }
+        }
+    }
+
+    impl<T: Buf> Encoder<T> for DelimitedCodec {
+        type Error = io::Error;
+
+        fn encode(&mut self, message: T, dst: &mut BytesMut) -> io::Result<()> {
+            let length = message.remaining() + 2;
+            let prefix = format!("{}\r\n", length);
+            dst.reserve(prefix.len() + length);
+            dst.put_slice(prefix.as_bytes());
+            dst.put(message);
+            dst.put_slice(b"\r\n");
+            Ok(())
+        }
+    }
+}
+
+#[cfg(all(test, feature = "codec"))]
+mod test {
+    use super::*;
+
+    use tokio_util::codec::{Decoder, Encoder};
+
+    fn decode_all<D>(mut codec: D, chunks: &[&[u8]]) -> Vec<Bytes>
+    where
+        D: Decoder<Item = Bytes, Error = io::Error>,
+    {
+        let mut buf = BytesMut::new();
+        let mut ret = Vec::new();
+        for chunk in chunks {
+            buf.extend_from_slice(chunk);
+            while let Some(message) = codec.decode(&mut buf).unwrap() {
+                ret.push(message);
+            }
+        }
+        while let Some(message) = codec.decode_eof(&mut buf).unwrap() {
+            ret.push(message);
+        }
+        ret
+    }
+
+    #[test]
+    fn crlf() {
+        let chunks: &[&[u8]] = &[b"{\"a\":1}\r", b"\n\r\n{\"b\"", b":\"\r\n\"}\r\n", b"{}"];
+        assert_eq!(
+            decode_all(CrlfCodec::new(), chunks),
+            [&b"{\"a\":1}"[..], b"", b"{\"b\":\"", b"\"}", b"{}"]
+        );
+        assert_eq!(
+            decode_all(CrlfCodec::new().skip_blank_lines(true), chunks),
+            [&b"{\"a\":1}"[..], b"{\"b\":\"", b"\"}", b"{}"]
+        );
+    }
+
+    #[test]
+    fn delimited() {
+        let mut encoded = BytesMut::new();
+        let mut codec = DelimitedCodec::new();
+        codec.encode(&b"{\"a\":1}"[..], &mut encoded).unwrap();
+        encoded.extend_from_slice(b"\r\n");
+        codec
+            .encode(&b"{\"b\":\"\r\n\"}"[..], &mut encoded)
+            .unwrap();
+        assert_eq!(
+            encoded,
+            &b"9\r\n{\"a\":1}\r\n\r\n12\r\n{\"b\":\"\r\n\"}\r\n"[..]
+        );
+
+        let chunks: Vec<&[u8]> = encoded.chunks(3).collect();
+        assert_eq!(
+            decode_all(DelimitedCodec::new(), &chunks),
+            [&b"{\"a\":1}"[..], b"", b"{\"b\":\"\r\n\"}"]
+        );
+        assert_eq!(
+            decode_all(DelimitedCodec::new().skip_blank_lines(true), &chunks),
+            [&b"{\"a\":1}"[..], b"{\"b\":\"\r\n\"}"]
+        );
+
+        let mut truncated = BytesMut::from(&b"9\r\n{\"a\""[..]);
+        assert!(DelimitedCodec::new().decode_eof(&mut truncated).is_err());
+    }
+
+    #[test]
+    fn max_length() {
+        let mut buf = BytesMut::from(&b"9\r\n{\"a\":1}\r\n10\r\n"[..]);
+        let mut codec = DelimitedCodec::new().max_length(9);
+        assert_eq!(codec.decode(&mut buf).unwrap().unwrap(), &b"{\"a\":1}"[..]);
+        let e = codec.decode(&mut buf).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+
+        let mut buf = BytesMut::from(&b"18446744073709551615\r\n"[..]);
+        let e = DelimitedCodec::new().decode(&mut buf).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,1045 @@
+//! Support for the compliance data: the [compliance firehose][firehose] of the enterprise API
+//! and the [batch compliance jobs][batch] of the Twitter API v2.
+//!
+//! [firehose]: https://developer.twitter.com/en/docs/twitter-api/enterprise/compliance-firehose-api/overview
+//! [batch]: https://developer.twitter.com/en/docs/twitter-api/compliance/batch-compliance/introduction
+//!
+//! The compliance firehose is connected with [`enterprise::Builder`] with
+//! [`Product::Compliance`], and the resulting [`TwitterStream`](crate::TwitterStream) is
+//! turned into a stream of typed [`Event`]s by [`ComplianceStream`]. The events can be applied
+//! to your own storage by implementing the [`Store`] trait.
+//!
+//! [`enterprise::Builder`]: crate::enterprise::Builder
+//! [`Product::Compliance`]: crate::enterprise::Product::Compliance
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! use std::collections::HashMap;
+//! use std::convert::Infallible;
+//!
+//! use futures::prelude::*;
+//! use twitter_stream::compliance::{ComplianceStream, Store};
+//! use twitter_stream::enterprise::{self, Product};
+//!
+//! #[derive(Default)]
+//! struct Tweets(HashMap<u64, String>);
+//!
+//! impl Store for Tweets {
+//!     type Error = Infallible;
+//!
+//!     fn delete_tweet(&mut self, tweet_id: u64, _user_id: u64) -> Result<(), Infallible> {
+//!         self.0.remove(&tweet_id);
+//!         Ok(())
+//!     }
+//!     // ...
+//! #   fn delete_user(&mut self, _: u64) -> Result<(), Infallible> { Ok(()) }
+//! #   fn suspend_user(&mut self, _: u64) -> Result<(), Infallible> { Ok(()) }
+//! #   fn scrub_geo(&mut self, _: u64, _: u64) -> Result<(), Infallible> { Ok(()) }
+//! #   fn withhold_tweet(&mut self, _: u64, _: u64, _: &[String]) -> Result<(), Infallible> { Ok(()) }
+//! #   fn withhold_user(&mut self, _: u64, _: &[String]) -> Result<(), Infallible> { Ok(()) }
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! let mut store = Tweets::default();
+//!
+//! let stream = enterprise::Builder::new("account", "prod", "username", "password")
+//!     .product(Product::Compliance)
+//!     .partition(1)
+//!     .listen()
+//!     .await
+//!     .unwrap();
+//!
+//! ComplianceStream::new(stream)
+//!     .try_for_each(|event| {
+//!         event.apply(&mut store).unwrap();
+//!         future::ok(())
+//!     })
+//!     .await
+//!     .unwrap();
+//! # }
+//! ```
+
+use std::borrow::Borrow;
+use std::error;
+use std::fmt::{self, Display, Formatter};
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures_core::{Stream, TryStream};
+use futures_util::ready;
+use http::header::{HeaderValue, InvalidHeaderValue, AUTHORIZATION, CONTENT_TYPE};
+use http::{Method, Request, StatusCode, Uri};
+use http_body::Body;
+use pin_project_lite::pin_project;
+use serde::de::{self, Deserialize, Deserializer, IgnoredAny, MapAccess, Visitor};
+use serde::Serialize;
+
+use crate::error::Error;
+use crate::service::HttpService;
+use crate::util::{collect_body, encode_path_segment};
+
+/// A compliance event.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Event {
+    /// A Tweet was deleted.
+    Delete {
+        /// ID of the deleted Tweet.
+        tweet_id: u64,
+        /// ID of the author of the Tweet.
+        user_id: u64,
+    },
+    /// A user deleted their account.
+    UserDelete {
+        /// ID of the user.
+        user_id: u64,
+    },
+    /// A user was suspended.
+    UserSuspend {
+        /// ID of the user.
+        user_id: u64,
+    },
+    /// A user removed the geolocation information from their Tweets.
+    ScrubGeo {
+        /// ID of the user.
+        user_id: u64,
+        /// The geolocation information must be removed from the Tweets of the user
+        /// up to this ID (inclusive).
+        up_to_tweet_id: u64,
+    },
+    /// A Tweet was withheld in some countries.
+    TweetWithheld {
+        /// ID of the withheld Tweet.
+        tweet_id: u64,
+        /// ID of the author of the Tweet.
+        user_id: u64,
+        /// The country codes of the countries where the Tweet is withheld.
+        withheld_in_countries: Vec<String>,
+    },
+    /// A user was withheld in some countries.
+    UserWithheld {
+        /// ID of the withheld user.
+        user_id: u64,
+        /// The country codes of the countries where the user is withheld.
+        withheld_in_countries: Vec<String>,
+    },
+    /// An event of an unrecognized type. The value is the name of the type
+    /// (e.g. `"user_protect"`).
+    Other(String),
+}
+
+/// A storage of Twitter content that compliance [`Event`]s can be applied to.
+pub trait Store {
+    /// The error type of the store.
+    type Error;
+
+    /// Removes the Tweet `tweet_id` of the user `user_id`.
+    fn delete_tweet(&mut self, tweet_id: u64, user_id: u64) -> Result<(), Self::Error>;
+
+    /// Removes the user `user_id` and their Tweets.
+    fn delete_user(&mut self, user_id: u64) -> Result<(), Self::Error>;
+
+    /// Hides the user `user_id` and their Tweets, who has been suspended.
+    fn suspend_user(&mut self, user_id: u64) -> Result<(), Self::Error>;
+
+    /// Removes the geolocation information from the Tweets of the user `user_id`
+    /// up to the Tweet `up_to_tweet_id` (inclusive).
+    fn scrub_geo(&mut self, user_id: u64, up_to_tweet_id: u64) -> Result<(), Self::Error>;
+
+    /// Hides the Tweet `tweet_id` of the user `user_id` in the countries `countries`.
+    fn withhold_tweet(
+        &mut self,
+        tweet_id: u64,
+        user_id: u64,
+        countries: &[String],
+    ) -> Result<(), Self::Error>;
+
+    /// Hides the user `user_id` in the countries `countries`.
+    fn withhold_user(&mut self, user_id: u64, countries: &[String]) -> Result<(), Self::Error>;
+}
+
+impl Event {
+    /// Applies the event to `store`.
+    ///
+    /// [`Event::Other`] is ignored.
+    pub fn apply<S: Store + ?Sized>(&self, store: &mut S) -> Result<(), S::Error> {
+        match *self {
+            Event::Delete { tweet_id, user_id } => store.delete_tweet(tweet_id, user_id),
+            Event::UserDelete { user_id } => store.delete_user(user_id),
+            Event::UserSuspend { user_id } => store.suspend_user(user_id),
+            Event::ScrubGeo {
+                user_id,
+                up_to_tweet_id,
+            } => store.scrub_geo(user_id, up_to_tweet_id),
+            Event::TweetWithheld {
+                tweet_id,
+                user_id,
+                ref withheld_in_countries,
+            } => store.withhold_tweet(tweet_id, user_id, withheld_in_countries),
+            Event::UserWithheld {
+                user_id,
+                ref withheld_in_countries,
+            } => store.withhold_user(user_id, withheld_in_countries),
+            Event::Other(_) => Ok(()),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Event {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        #[derive(serde::Deserialize)]
+        struct Delete {
+            status: Status,
+        }
+
+        #[derive(serde::Deserialize)]
+        struct Status {
+            id: u64,
+            user_id: u64,
+        }
+
+        #[derive(serde::Deserialize)]
+        struct User {
+            id: u64,
+        }
+
+        #[derive(serde::Deserialize)]
+        struct ScrubGeo {
+            user_id: u64,
+            up_to_status_id: u64,
+        }
+
+        #[derive(serde::Deserialize)]
+        struct StatusWithheld {
+            id: u64,
+            user_id: u64,
+            withheld_in_countries: Vec<String>,
+        }
+
+        #[derive(serde::Deserialize)]
+        struct UserWithheld {
+            id: u64,
+            withheld_in_countries: Vec<String>,
+        }
+
+        struct EventVisitor;
+
+        impl<'de> Visitor<'de> for EventVisitor {
+            type Value = Event;
+
+            fn expecting(&self, f: &mut Formatter<'_>) -> fmt::Result {
+                f.write_str("a compliance event object")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut a: A) -> Result<Event, A::Error> {
+                let kind: String = a
+                    .next_key()?
+                    .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+                let event = match &*kind {
+                    "delete" => {
+                        let Delete { status } = a.next_value()?;
+                        Event::Delete {
+                            tweet_id: status.id,
+                            user_id: status.user_id,
+                        }
+                    }
+                    "user_delete" => Event::UserDelete {
+                        user_id: a.next_value::<User>()?.id,
+                    },
+                    "user_suspend" => Event::UserSuspend {
+                        user_id: a.next_value::<User>()?.id,
+                    },
+                    "scrub_geo" => {
+                        let v: ScrubGeo = a.next_value()?;
+                        Event::ScrubGeo {
+                            user_id: v.user_id,
+                            up_to_tweet_id: v.up_to_status_id,
+                        }
+                    }
+                    "status_withheld" => {
+                        let v: StatusWithheld = a.next_value()?;
+                        Event::TweetWithheld {
+                            tweet_id: v.id,
+                            user_id: v.user_id,
+                            withheld_in_countries: v.withheld_in_countries,
+                        }
+                    }
+                    "user_withheld" => {
+                        let v: UserWithheld = a.next_value()?;
+                        Event::UserWithheld {
+                            user_id: v.id,
+                            withheld_in_countries: v.withheld_in_countries,
+                        }
+                    }
+                    _ => {
+                        a.next_value::<IgnoredAny>()?;
+                        Event::Other(kind)
+                    }
+                };
+                while a.next_entry::<IgnoredAny, IgnoredAny>()?.is_some() {}
+                Ok(event)
+            }
+        }
+
+        d.deserialize_map(EventVisitor)
+    }
+}
+
+pin_project! {
+    /// A stream of the compliance firehose, yielding typed [`Event`]s.
+    ///
+    /// This wraps a stream of JSON strings like [`TwitterStream`](crate::TwitterStream).
+    /// To consume several partitions at once, map the [`Tagged`](crate::partition::Tagged)
+    /// messages of a [`Partitioned`](crate::partition::Partitioned) stream to their `json`
+    /// field.
+    pub struct ComplianceStream<S> {
+        #[pin]
+        inner: S,
+    }
+}
+
+/// An error yielded by [`ComplianceStream`].
+#[derive(Debug)]
+pub enum ComplianceError<E = Error> {
+    /// The underlying stream yielded an error.
+    Stream(E),
+    /// The stream yielded a malformed compliance event.
+    Json(serde_json::Error),
+}
+
+impl<S> ComplianceStream<S> {
+    /// Creates a `ComplianceStream` yielding the events parsed from the messages of `stream`.
+    pub fn new(stream: S) -> Self {
+        ComplianceStream { inner: stream }
+    }
+
+    /// Returns a reference to the underlying stream.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Consumes the `ComplianceStream`, returning the underlying stream.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S> Stream for ComplianceStream<S>
+where
+    S: TryStream,
+    S::Ok: Borrow<str>,
+{
+    type Item = Result<Event, ComplianceError<S::Error>>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let json = match ready!(self.project().inner.try_poll_next(cx)) {
+            Some(Ok(json)) => json,
+            Some(Err(e)) => return Poll::Ready(Some(Err(ComplianceError::Stream(e)))),
+            None => return Poll::Ready(None),
+        };
+        let event = serde_json::from_str(json.borrow()).map_err(ComplianceError::Json);
+        Poll::Ready(Some(event))
+    }
+}
+
+impl<E: error::Error + 'static> error::Error for ComplianceError<E> {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            ComplianceError::Stream(ref e) => Some(e),
+            ComplianceError::Json(ref e) => Some(e),
+        }
+    }
+}
+
+impl<E: Display> Display for ComplianceError<E> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match *self {
+            ComplianceError::Stream(ref e) => Display::fmt(e, f),
+            ComplianceError::Json(ref e) => write!(f, "JSON error: {}", e),
+        }
+    }
+}
+
+/// A client for the v2 [batch compliance jobs][1].
+///
+/// [1]: https://developer.twitter.com/en/docs/twitter-api/compliance/batch-compliance/introduction
+///
+/// The `Debug` representation of `Jobs` omits the bearer token.
+#[derive(Clone)]
+pub struct Jobs<S> {
+    authorization: HeaderValue,
+    client: S,
+}
+
+str_enum! {
+    /// The type of the IDs in a compliance job.
+    #[derive(Clone, Copy, Debug, PartialEq, Hash, Eq, Serialize, serde::Deserialize)]
+    #[serde(rename_all = "snake_case")]
+    pub enum JobType {
+        /// `"tweets"`
+        Tweets = "tweets",
+        /// `"users"`
+        Users = "users",
+    }
+}
+
+str_enum! {
+    /// The status of a compliance job.
+    #[derive(Clone, Copy, Debug, PartialEq, Hash, Eq, serde::Deserialize)]
+    #[serde(rename_all = "snake_case")]
+    pub enum JobStatus {
+        /// `"created"`
+        Created = "created",
+        /// `"in_progress"`
+        InProgress = "in_progress",
+        /// `"failed"`
+        Failed = "failed",
+        /// `"complete"`
+        Complete = "complete",
+        /// `"expired"`
+        Expired = "expired",
+    }
+}
+
+/// A batch compliance job.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, serde::Deserialize)]
+pub struct Job {
+    /// ID of the job.
+    pub id: String,
+    /// The type of the IDs in the job.
+    #[serde(rename = "type")]
+    pub job_type: JobType,
+    /// The name of the job.
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Whether the upload URL can be resumed.
+    #[serde(default)]
+    pub resumable: bool,
+    /// The URL to upload the IDs to, with [`Jobs::upload`].
+    pub upload_url: String,
+    /// The expiration time of `upload_url` in ISO 8601 format.
+    pub upload_expires_at: String,
+    /// The URL to download the results from, with [`Jobs::download`].
+    pub download_url: String,
+    /// The expiration time of `download_url` in ISO 8601 format.
+    pub download_expires_at: String,
+    /// The status of the job.
+    pub status: JobStatus,
+    /// The creation time of the job in ISO 8601 format.
+    pub created_at: String,
+}
+
+/// A result of a compliance job, representing an ID which requires a compliance action.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, serde::Deserialize)]
+pub struct JobResult {
+    /// The Tweet or user ID.
+    pub id: String,
+    /// The action to be taken (e.g. `"delete"`).
+    pub action: String,
+    /// The reason of the action (e.g. `"deleted"`, `"suspended"` or `"scrub_geo"`).
+    #[serde(default)]
+    pub reason: Option<String>,
+    /// The creation time of the Tweet or user in ISO 8601 format.
+    #[serde(default)]
+    pub created_at: Option<String>,
+    /// The time of the compliance event in ISO 8601 format.
+    #[serde(default)]
+    pub redacted_at: Option<String>,
+}
+
+/// An error occurred while calling the batch compliance API.
+#[derive(Debug)]
+pub enum JobsError<E = Box<dyn error::Error + Send + Sync>> {
+    /// An error occurred while sending the request or receiving the response.
+    Request(Error<E>),
+    /// The server rejected the request with the given status code and error message.
+    Api(StatusCode, String),
+    /// The server returned a malformed response.
+    Json(serde_json::Error),
+    /// The given job ID cannot be embedded in the request URI (e.g. it is empty or `..`).
+    InvalidId(String),
+}
+
+const JOBS: &str = "https://api.twitter.com/2/compliance/jobs";
+
+/// Creates a client for the batch compliance jobs, authenticating with `bearer_token`.
+///
+/// Returns an error if `bearer_token` contains characters not allowed in an HTTP header value.
+///
+/// # Panics
+///
+/// This will panic if the underlying HTTPS connector failed to initialize.
+#[cfg(feature = "hyper")]
+#[cfg_attr(docsrs, doc(cfg(feature = "hyper")))]
+pub fn jobs(bearer_token: &str) -> Result<Jobs<crate::hyper::Client>, InvalidHeaderValue> {
+    jobs_with_client(bearer_token, crate::util::https_client())
+}
+
+/// Same as [`jobs`] except that the `Jobs` uses `client` to make HTTP requests.
+pub fn jobs_with_client<S>(bearer_token: &str, client: S) -> Result<Jobs<S>, InvalidHeaderValue> {
+    let mut authorization = HeaderValue::from_str(&format!("Bearer {}", bearer_token))?;
+    authorization.set_sensitive(true);
+    Ok(Jobs {
+        authorization,
+        client,
+    })
+}
+
+#[derive(serde::Deserialize)]
+struct Data<T> {
+    data: T,
+}
+
+#[derive(Serialize)]
+struct CreateJob<'a> {
+    #[serde(rename = "type")]
+    job_type: JobType,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<&'a str>,
+    resumable: bool,
+}
+
+impl<S> Jobs<S> {
+    /// Creates a compliance job for the IDs of `job_type`.
+    pub fn create<B>(
+        &mut self,
+        job_type: JobType,
+        name: Option<&str>,
+        resumable: bool,
+    ) -> impl Future<Output = Result<Job, JobsError<S::Error>>>
+    where
+        S: HttpService<B, Response = http::Response<<S as HttpService<B>>::ResponseBody>>,
+        <S::ResponseBody as Body>::Error: Into<S::Error>,
+        B: From<Vec<u8>>,
+    {
+        let body = CreateJob {
+            job_type,
+            name,
+            resumable,
+        };
+        let req = Request::post(JOBS)
+            .header(AUTHORIZATION, self.authorization.clone())
+            .header(CONTENT_TYPE, HeaderValue::from_static("application/json"))
+            .body(serde_json::to_vec(&body).unwrap())
+            .unwrap();
+        let res = self.send(req);
+        async move { Ok(parse::<Data<Job>, _>(res.await?)?.data) }
+    }
+
+    /// Retrieves the compliance job of `id`.
+    ///
+    /// `id` is percent-encoded in the request URI. The future resolves to
+    /// [`JobsError::InvalidId`] without sending a request if `id` is empty, `.` or `..`.
+    pub fn get<B>(&mut self, id: &str) -> impl Future<Output = Result<Job, JobsError<S::Error>>>
+    where
+        S: HttpService<B, Response = http::Response<<S as HttpService<B>>::ResponseBody>>,
+        <S::ResponseBody as Body>::Error: Into<S::Error>,
+        B: From<Vec<u8>>,
+    {
+        let uri = match id {
+            "" | "." | ".." => None,
+            _ => format!("{}/{}", JOBS, encode_path_segment(id.into()))
+                .parse::<Uri>()
+                .ok(),
+        };
+        let res = match uri {
+            Some(uri) => {
+                let req = Request::get(uri)
+                    .header(AUTHORIZATION, self.authorization.clone())
+                    .body(Vec::new())
+                    .unwrap();
+                Ok(self.send(req))
+            }
+            None => Err(JobsError::InvalidId(id.to_owned())),
+        };
+        async move { Ok(parse::<Data<Job>, _>(res?.await?)?.data) }
+    }
+
+    /// Lists the compliance jobs for the IDs of `job_type`.
+    pub fn list<B>(
+        &mut self,
+        job_type: JobType,
+    ) -> impl Future<Output = Result<Vec<Job>, JobsError<S::Error>>>
+    where
+        S: HttpService<B, Response = http::Response<<S as HttpService<B>>::ResponseBody>>,
+        <S::ResponseBody as Body>::Error: Into<S::Error>,
+        B: From<Vec<u8>>,
+    {
+        let req = Request::get(format!("{}?type={}", JOBS, job_type.as_ref()))
+            .header(AUTHORIZATION, self.authorization.clone())
+            .body(Vec::new())
+            .unwrap();
+        let res = self.send(req);
+        async move {
+            #[derive(serde::Deserialize)]
+            struct List {
+                #[serde(default)]
+                data: Vec<Job>,
+            }
+            Ok(parse::<List, _>(res.await?)?.data)
+        }
+    }
+
+    /// Uploads the Tweet or user IDs to be checked to the `upload_url` of `job`.
+    pub fn upload<B>(
+        &mut self,
+        job: &Job,
+        ids: &[u64],
+    ) -> impl Future<Output = Result<(), JobsError<S::Error>>>
+    where
+        S: HttpService<B, Response = http::Response<<S as HttpService<B>>::ResponseBody>>,
+        <S::ResponseBody as Body>::Error: Into<S::Error>,
+        B: From<Vec<u8>>,
+    {
+        let mut body = String::new();
+        for id in ids {
+            body.push_str(&id.to_string());
+            body.push('\n');
+        }
+        let req = Request::put(&*job.upload_url)
+            .header(CONTENT_TYPE, HeaderValue::from_static("text/plain"))
+            .body(body.into_bytes())
+            .unwrap();
+        let res = self.send(req);
+        async move { res.await.map(|_| ()) }
+    }
+
+    /// Downloads the results of the completed `job` from its `download_url`.
+    pub fn download<B>(
+        &mut self,
+        job: &Job,
+    ) -> impl Future<Output = Result<Vec<JobResult>, JobsError<S::Error>>>
+    where
+        S: HttpService<B, Response = http::Response<<S as HttpService<B>>::ResponseBody>>,
+        <S::ResponseBody as Body>::Error: Into<S::Error>,
+        B: From<Vec<u8>>,
+    {
+        let req = Request::builder()
+            .method(Method::GET)
+            .uri(&*job.download_url)
+            .body(Vec::new())
+            .unwrap();
+        let res = self.send(req);
+        async move {
+            let body = res.await?;
+            body.split(|&b| b == b'\n')
+                .filter(|line| !line.iter().all(u8::is_ascii_whitespace))
+                .map(|line| serde_json::from_slice(line).map_err(JobsError::Json))
+                .collect()
+        }
+    }
+
+    fn send<B>(
+        &mut self,
+        req: Request<Vec<u8>>,
+    ) -> impl Future<Output = Result<Vec<u8>, JobsError<S::Error>>>
+    where
+        S: HttpService<B, Response = http::Response<<S as HttpService<B>>::ResponseBody>>,
+        <S::ResponseBody as Body>::Error: Into<S::Error>,
+        B: From<Vec<u8>>,
+    {
+        let res = self.client.call(req.map(Into::into));
+        async move {
+            let res = res
+                .await
+                .map_err(|e| JobsError::Request(Error::Service(e)))?;
+            let status = res.status();
+            let body = collect_body(res.into_body())
+                .await
+                .map_err(|e| JobsError::Request(e.map_service(Into::into)))?;
+            if !status.is_success() {
+                let message = String::from_utf8_lossy(&body).into_owned();
+                return Err(JobsError::Api(status, message));
+            }
+            Ok(body)
+        }
+    }
+}
+
+impl<S> fmt::Debug for Jobs<S> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Jobs")
+            .field("authorization", &format_args!("<hidden>"))
+            .finish()
+    }
+}
+
+impl<E: error::Error + 'static> error::Error for JobsError<E> {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            JobsError::Request(ref e) => Some(e),
+            JobsError::Api(..) => None,
+            JobsError::Json(ref e) => Some(e),
+            JobsError::InvalidId(_) => None,
+        }
+    }
+}
+
+impl<E: Display> Display for JobsError<E> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match *self {
+            JobsError::Request(ref e) => Display::fmt(e, f),
+            JobsError::Api(status, ref message) => write!(f, "{}: {}", status, message),
+            JobsError::Json(ref e) => write!(f, "JSON error: {}", e),
+            JobsError::InvalidId(ref id) => write!(f, "invalid job ID: {:?}", id),
+        }
+    }
+}
+
+fn parse<T: de::DeserializeOwned, E>(body: Vec<u8>) -> Result<T, JobsError<E>> {
+    serde_json::from_slice(&body).map_err(JobsError::Json)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_events() {
+        let events = [
+            (
+                r#"{"delete":{"status":{"id":601430178305220608,"id_str":"601430178305220608","user_id":3198576760,"user_id_str":"3198576760"},"timestamp_ms":"1432228155593"}}"#,
+                Event::Delete {
+                    tweet_id: 601430178305220608,
+                    user_id: 3198576760,
+                },
+            ),
+            (
+                r#"{"user_suspend":{"id":1234567890,"id_str":"1234567890","timestamp_ms":"1432228155593"}}"#,
+                Event::UserSuspend {
+                    user_id: 1234567890,
+                },
+            ),
+            (
+                r#"{"scrub_geo":{"user_id":14090452,"user_id_str":"14090452","up_to_status_id":23260136625,"up_to_status_id_str":"23260136625","timestamp_ms":"1432228155593"}}"#,
+                Event::ScrubGeo {
+                    user_id: 14090452,
+                    up_to_tweet_id: 23260136625,
+                },
+            ),
+            (
+                r#"{"status_withheld":{"id":1234567890,"user_id":123456,"withheld_in_countries":["DE","AR"],"timestamp_ms":"1432228155593"}}"#,
+                Event::TweetWithheld {
+                    tweet_id: 1234567890,
+                    user_id: 123456,
+                    withheld_in_countries: vec!["DE".to_owned(), "AR".to_owned()],
+                },
+            ),
+            (
+                r#"{"user_protect":{"id":1234567890,"timestamp_ms":"1432228155593"}}"#,
+                Event::Other("user_protect".to_owned()),
+            ),
+        ];
+
+        for (json, expected) in &events {
+            assert_eq!(serde_json::from_str::<Event>(json).unwrap(), *expected);
+        }
+    }
+
+    #[derive(Debug, Default, PartialEq)]
+    struct Calls(Vec<String>);
+
+    impl Store for Calls {
+        type Error = ();
+
+        fn delete_tweet(&mut self, tweet_id: u64, user_id: u64) -> Result<(), ()> {
+            self.0
+                .push(format!("delete_tweet {} {}", tweet_id, user_id));
+            Ok(())
+        }
+
+        fn delete_user(&mut self, user_id: u64) -> Result<(), ()> {
+            self.0.push(format!("delete_user {}", user_id));
+            Ok(())
+        }
+
+        fn suspend_user(&mut self, user_id: u64) -> Result<(), ()> {
+            self.0.push(format!("suspend_user {}", user_id));
+            Ok(())
+        }
+
+        fn scrub_geo(&mut self, user_id: u64, up_to_tweet_id: u64) -> Result<(), ()> {
+            self.0
+                .push(format!("scrub_geo {} {}", user_id, up_to_tweet_id));
+            Ok(())
+        }
+
+        fn withhold_tweet(
+            &mut self,
+            tweet_id: u64,
+            user_id: u64,
+            countries: &[String],
+        ) -> Result<(), ()> {
+            let countries = countries.join(",");
+            self.0.push(format!(
+                "withhold_tweet {} {} {}",
+                tweet_id, user_id, countries
+            ));
+            Ok(())
+        }
+
+        fn withhold_user(&mut self, user_id: u64, countries: &[String]) -> Result<(), ()> {
+            self.0
+                .push(format!("withhold_user {} {}", user_id, countries.join(",")));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn apply_events() {
+        let countries = || vec!["DE".to_owned(), "AR".to_owned()];
+        let events = [
+            Event::Delete {
+                tweet_id: 1,
+                user_id: 2,
+            },
+            Event::UserDelete { user_id: 3 },
+            Event::UserSuspend { user_id: 4 },
+            Event::ScrubGeo {
+                user_id: 5,
+                up_to_tweet_id: 6,
+            },
+            Event::TweetWithheld {
+                tweet_id: 7,
+                user_id: 8,
+                withheld_in_countries: countries(),
+            },
+            Event::UserWithheld {
+                user_id: 9,
+                withheld_in_countries: countries(),
+            },
+            Event::Other("user_protect".to_owned()),
+        ];
+
+        let mut store = Calls::default();
+        for event in &events {
+            event.apply(&mut store).unwrap();
+        }
+        assert_eq!(
+            store.0,
+            [
+                "delete_tweet 1 2",
+                "delete_user 3",
+                "suspend_user 4",
+                "scrub_geo 5 6",
+                "withhold_tweet 7 8 DE,AR",
+                "withhold_user 9 DE,AR",
+            ]
+        );
+    }
+
+    #[test]
+    fn compliance_stream() {
+        use futures::executor::block_on;
+        use futures::stream::{self, StreamExt};
+
+        let messages = vec![
+            Ok(r#"{"user_delete":{"id":3,"timestamp_ms":"1432228155593"}}"#),
+            Ok(r#"{"user_delete":{}}"#),
+            Err("disconnected"),
+        ];
+        let mut events =
+            block_on(ComplianceStream::new(stream::iter(messages)).collect::<Vec<_>>()).into_iter();
+
+        match events.next() {
+            Some(Ok(Event::UserDelete { user_id: 3 })) => {}
+            event => panic!("unexpected event: {:?}", event),
+        }
+        match events.next() {
+            Some(Err(ComplianceError::Json(_))) => {}
+            event => panic!("unexpected event: {:?}", event),
+        }
+        match events.next() {
+            Some(Err(ComplianceError::Stream("disconnected"))) => {}
+            event => panic!("unexpected event: {:?}", event),
+        }
+        assert!(events.next().is_none());
+    }
+
+    const JOB: &str = r#"{
+        "id": "1382081613278814209",
+        "type": "tweets",
+        "name": "my-job",
+        "resumable": false,
+        "upload_url": "https://storage.googleapis.com/twttr-tweet-compliance/upload",
+        "upload_expires_at": "2021-04-13T21:09:35.000Z",
+        "download_url": "https://storage.googleapis.com/twttr-tweet-compliance/download",
+        "download_expires_at": "2021-04-20T20:54:35.000Z",
+        "status": "created",
+        "created_at": "2021-04-13T20:54:35.000Z"
+    }"#;
+
+    fn job() -> Job {
+        serde_json::from_str(JOB).unwrap()
+    }
+
+    fn jobs_client(
+        method: Method,
+        uri: &'static str,
+        body: &'static str,
+        status: StatusCode,
+        response: String,
+    ) -> Jobs<
+        impl HttpService<
+            Vec<u8>,
+            Response = http::Response<hyper_pkg::Body>,
+            Error = hyper_pkg::Error,
+            ResponseBody = hyper_pkg::Body,
+        >,
+    > {
+        let client = tower::service_fn(move |req: http::Request<Vec<u8>>| {
+            assert_eq!(req.method(), method);
+            assert_eq!(req.uri(), uri);
+            assert_eq!(std::str::from_utf8(req.body()).unwrap(), body);
+            // The bearer token must not be sent to the upload and download URLs.
+            let authorization = req.headers().get(AUTHORIZATION);
+            if uri.starts_with(JOBS) {
+                assert_eq!(authorization.unwrap(), "Bearer AAAA");
+            } else {
+                assert_eq!(authorization, None);
+            }
+            let res = http::Response::builder()
+                .status(status)
+                .body(hyper_pkg::Body::from(response.clone()))
+                .unwrap();
+            futures::future::ok::<_, hyper_pkg::Error>(res)
+        });
+        jobs_with_client("AAAA", client).unwrap()
+    }
+
+    #[test]
+    fn jobs() {
+        use futures::executor::block_on;
+
+        let data = format!(r#"{{"data":{}}}"#, JOB);
+
+        let mut jobs = jobs_client(
+            Method::POST,
+            JOBS,
+            r#"{"type":"tweets","name":"my-job","resumable":false}"#,
+            StatusCode::OK,
+            data.clone(),
+        );
+        let job = block_on(jobs.create(JobType::Tweets, Some("my-job"), false)).unwrap();
+        assert_eq!(job.id, "1382081613278814209");
+        assert_eq!(job.job_type, JobType::Tweets);
+        assert_eq!(job.name.as_deref(), Some("my-job"));
+        assert_eq!(job.status, JobStatus::Created);
+
+        let mut jobs = jobs_client(
+            Method::GET,
+            "https://api.twitter.com/2/compliance/jobs/1382081613278814209",
+            "",
+            StatusCode::OK,
+            data,
+        );
+        assert_eq!(block_on(jobs.get("1382081613278814209")).unwrap(), job);
+
+        let mut jobs = jobs_client(
+            Method::GET,
+            "https://api.twitter.com/2/compliance/jobs?type=users",
+            "",
+            StatusCode::OK,
+            "{}".to_owned(),
+        );
+        assert_eq!(block_on(jobs.list(JobType::Users)).unwrap(), []);
+
+        let mut jobs = jobs_client(
+            Method::GET,
+            "https://api.twitter.com/2/compliance/jobs?type=tweets",
+            "",
+            StatusCode::OK,
+            format!(r#"{{"data":[{}]}}"#, JOB),
+        );
+        assert_eq!(block_on(jobs.list(JobType::Tweets)).unwrap(), [job]);
+
+        let mut jobs = jobs_client(
+            Method::GET,
+            "https://api.twitter.com/2/compliance/jobs/0",
+            "",
+            StatusCode::BAD_REQUEST,
+            "invalid id".to_owned(),
+        );
+        match block_on(jobs.get("0")) {
+            Err(JobsError::Api(StatusCode::BAD_REQUEST, ref message)) => {
+                assert_eq!(message, "invalid id")
+            }
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn upload_and_download() {
+        use futures::executor::block_on;
+
+        let mut jobs = jobs_client(
+            Method::PUT,
+            "https://storage.googleapis.com/twttr-tweet-compliance/upload",
+            "1\n2\n",
+            StatusCode::OK,
+            String::new(),
+        );
+        block_on(jobs.upload(&job(), &[1, 2])).unwrap();
+
+        let results = r#"{"id":"1","action":"delete","reason":"deleted","created_at":"2021-04-13T20:54:35.000Z","redacted_at":"2021-04-14T20:54:35.000Z"}
+{"id":"2","action":"delete","reason":"suspended"}
+
+"#;
+        let mut jobs = jobs_client(
+            Method::GET,
+            "https://storage.googleapis.com/twttr-tweet-compliance/download",
+            "",
+            StatusCode::OK,
+            results.to_owned(),
+        );
+        let results = block_on(jobs.download(&job())).unwrap();
+        assert_eq!(
+            results,
+            [
+                JobResult {
+                    id: "1".to_owned(),
+                    action: "delete".to_owned(),
+                    reason: Some("deleted".to_owned()),
+                    created_at: Some("2021-04-13T20:54:35.000Z".to_owned()),
+                    redacted_at: Some("2021-04-14T20:54:35.000Z".to_owned()),
+                },
+                JobResult {
+                    id: "2".to_owned(),
+                    action: "delete".to_owned(),
+                    reason: Some("suspended".to_owned()),
+                    created_at: None,
+                    redacted_at: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn get_escapes_id() {
+        use futures::executor::block_on;
+
+        let data = format!(r#"{{"data":{}}}"#, JOB);
+        let mut jobs = jobs_client(
+            Method::GET,
+            "https://api.twitter.com/2/compliance/jobs/..%2Fjobs%3Ftype%3Dusers",
+            "",
+            StatusCode::OK,
+            data,
+        );
+        block_on(jobs.get("../jobs?type=users")).unwrap();
+
+        for &id in &["", ".", ".."] {
+            match block_on(jobs.get(id)) {
+                Err(JobsError::InvalidId(ref e)) if e == id => {}
+                r => panic!("unexpected result: {:?}", r),
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_bearer_token() {
+        assert!(jobs_with_client("AAAA\n", ()).is_err());
+    }
+}
@@ -0,0 +1,472 @@
+//! Deduplication of Tweets by their IDs.
+//!
+//! Reconnections (especially with the `backfill_minutes`/`count` parameters), overlapping
+//! filters and multiple connections all deliver the same Tweets more than once. [`Dedup`] wraps
+//! a stream of JSON messages and discards the Tweets whose IDs it has seen within a [`Window`].
+//!
+//! The Tweet IDs are extracted with [`scan`](crate::scan) without parsing the whole messages.
+//! The messages without a Tweet ID (e.g. `delete` and `limit` notices) are passed through.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! use futures::prelude::*;
+//! use twitter_stream::dedup::{Dedup, Window};
+//! use twitter_stream::Token;
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! let token = Token::new("consumer_key", "consumer_secret", "access_key", "access_secret");
+//!
+//! let stream = twitter_stream::Builder::new(token)
+//!     .track("@NASA")
+//!     .listen()
+//!     .try_flatten_stream();
+//!
+//! Dedup::new(stream, Window::Count(100_000))
+//!     .try_for_each(|json| {
+//!         println!("{}", json);
+//!         future::ok(())
+//!     })
+//!     .await
+//!     .unwrap();
+//! # }
+//! ```
+
+use std::borrow::Borrow;
+use std::collections::{HashSet, VecDeque};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use futures_core::{Stream, TryStream};
+use futures_util::ready;
+use pin_project_lite::pin_project;
+
+use crate::scan;
+
+pin_project! {
+    /// A stream discarding the Tweets whose IDs have been seen within a [`Window`].
+    pub struct Dedup<S> {
+        #[pin]
+        inner: S,
+        seen: Seen,
+        dropped: u64,
+    }
+}
+
+/// The range of the past Tweets for [`Dedup`] to remember.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Window {
+    /// Remember the given number of the most recent Tweet IDs.
+    Count(usize),
+    /// Remember the Tweet IDs seen within the given duration.
+    Duration(Duration),
+}
+
+enum Seen {
+    Exact(Exact),
+    Bloom(Bloom),
+}
+
+struct Exact {
+    window: Window,
+    ids: HashSet<u64>,
+    queue: VecDeque<(u64, Instant)>,
+}
+
+/// A pair of Bloom filters, the older of which is discarded when the newer one has covered
+/// the window.
+struct Bloom {
+    window: Window,
+    hashes: u32,
+    current: BloomFilter,
+    previous: BloomFilter,
+}
+
+struct BloomFilter {
+    bits: Vec<u64>,
+    len: usize,
+    since: Instant,
+}
+
+impl<S> Dedup<S> {
+    /// Creates a `Dedup` discarding the Tweets from `stream` whose IDs have been seen within
+    /// `window`.
+    ///
+    /// The IDs are remembered exactly, which takes memory proportional to the number of the IDs
+    /// in the window.
+    pub fn new(stream: S, window: Window) -> Self {
+        Dedup {
+            inner: stream,
+            seen: Seen::Exact(Exact {
+                window,
+                ids: HashSet::new(),
+                queue: VecDeque::new(),
+            }),
+            dropped: 0,
+        }
+    }
+
+    /// Creates a `Dedup` remembering the IDs with Bloom filters sized for `expected_items` IDs
+    /// in `window`.
+    ///
+    /// This takes constant memory regardless of the size of the window, at the cost of
+    /// discarding a non-duplicate Tweet with a probability of about `false_positive_rate`.
+    /// The IDs seen before `window` may be remembered for up to another `window`.
+    ///
+    /// # Panics
+    ///
+    /// This will panic if `expected_items` is zero or `false_positive_rate` is not between
+    /// `0` and `1` (exclusive).
+    pub fn probabilistic(
+        stream: S,
+        window: Window,
+        expected_items: usize,
+        false_positive_rate: f64,
+    ) -> Self {
+        assert!(expected_items > 0, "expected_items must be positive");
+        assert!(
+            false_positive_rate > 0. && false_positive_rate < 1.,
+            "false_positive_rate must be between 0 and 1"
+        );
+
+        let ln2 = std::f64::consts::LN_2;
+        let bits = (-(expected_items as f64) * false_positive_rate.ln() / (ln2 * ln2)).ceil();
+        let bits = (bits as usize).max(64);
+        let hashes = ((bits as f64 / expected_items as f64) * ln2)
+            .round()
+            .max(1.) as u32;
+
+        let now = Instant::now();
+        Dedup {
+            inner: stream,
+            seen: Seen::Bloom(Bloom {
+                window,
+                hashes,
+                current: BloomFilter::new(bits, now),
+                previous: BloomFilter::new(bits, now),
+            }),
+            dropped: 0,
+        }
+    }
+
+    /// Returns the number of the Tweets discarded as duplicates so far.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// Returns a reference to the underlying stream.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Consumes the `Dedup`, returning the underlying stream.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S> Stream for Dedup<S>
+where
+    S: TryStream,
+    S::Ok: Borrow<str>,
+{
+    type Item = Result<S::Ok, S::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+        loop {
+            let json = match ready!(this.inner.as_mut().try_poll_next(cx)?) {
+                Some(json) => json,
+                None => return Poll::Ready(None),
+            };
+            match tweet_id(json.borrow()) {
+                Some(id) if !this.seen.insert(id, Instant::now()) => *this.dropped += 1,
+                _ => return Poll::Ready(Some(Ok(json))),
+            }
+        }
+    }
+}
+
+impl<S> std::fmt::Debug for Dedup<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (mode, window) = match self.seen {
+            Seen::Exact(ref exact) => ("exact", exact.window),
+            Seen::Bloom(ref bloom) => ("probabilistic", bloom.window),
+        };
+        f.debug_struct("Dedup")
+            .field("mode", &mode)
+            .field("window", &window)
+            .field("dropped", &self.dropped)
+            .finish()
+    }
+}
+
+/// Extracts the Tweet ID from a Tweet object of the v1.1 API (`id`) or the v2 API (`data.id`).
+///
+/// Returns `None` if `json` is not a Tweet.
+pub fn tweet_id(json: &str) -> Option<u64> {
+    let mut values = [None; 2];
+    scan::scan(json, &[&["id"], &["data", "id"]], &mut values).ok()?;
+    let [id, data_id] = values;
+    id.and_then(|id| id.as_u64())
+        .or_else(|| data_id?.as_str()?.parse().ok())
+}
+
+impl Seen {
+    /// Records `id`, returning `false` if it has been seen within the window.
+    fn insert(&mut self, id: u64, now: Instant) -> bool {
+        match *self {
+            Seen::Exact(ref mut exact) => exact.insert(id, now),
+            Seen::Bloom(ref mut bloom) => bloom.insert(id, now),
+        }
+    }
+}
+
+impl Exact {
+    fn insert(&mut self, id: u64, now: Instant) -> bool {
+        if let Window::Duration(duration) = self.window {
+            while let Some(&(old, at)) = self.queue.front() {
+                if now.duration_since(at) < duration {
+                    break;
+                }
+                self.queue.pop_front();
+                self.ids.remove(&old);
+            }
+        }
+
+        if self.ids.contains(&id) {
+            return false;
+        }
+
+        if let Window::Count(count) = self.window {
+            while self.queue.len() >= count.max(1) {
+                let (old, _) = self.queue.pop_front().unwrap();
+                self.ids.remove(&old);
+            }
+        }
+        self.ids.insert(id);
+        self.queue.push_back((id, now));
+        true
+    }
+}
+
+impl Bloom {
+    fn insert(&mut self, id: u64, now: Instant) -> bool {
+        // The number of the windows the current filter has covered.
+        let windows = match self.window {
+            Window::Count(count) => (self.current.len >= count) as u32,
+            Window::Duration(duration) => {
+                let elapsed = now.duration_since(self.current.since);
+                if elapsed < duration {
+                    0
+                } else if elapsed - duration < duration {
+                    1
+                } else {
+                    2
+                }
+            }
+        };
+        if windows > 0 {
+            // After an idle period of two windows, the current filter is stale as well.
+            if windows > 1 {
+                self.current.clear(now);
+            }
+            self.previous.clear(now);
+            std::mem::swap(&mut self.current, &mut self.previous);
+        }
+
+        let (h1, h2) = hash(id);
+        if self.current.contains(h1, h2, self.hashes) || self.previous.contains(h1, h2, self.hashes)
+        {
+            return false;
+        }
+        self.current.insert(h1, h2, self.hashes);
+        true
+    }
+}
+
+impl BloomFilter {
+    // `usize::div_ceil` requires Rust 1.73.
+    #[allow(clippy::manual_div_ceil)]
+    fn new(bits: usize, since: Instant) -> Self {
+        BloomFilter {
+            bits: vec![0; (bits + 63) / 64],
+            len: 0,
+            since,
+        }
+    }
+
+    fn clear(&mut self, since: Instant) {
+        for word in &mut self.bits {
+            *word = 0;
+        }
+        self.len = 0;
+        self.since = since;
+    }
+
+    fn index(&self, h1: u64, h2: u64, i: u32) -> usize {
+        let bits = self.bits.len() as u64 * 64;
+        (h1.wrapping_add(u64::from(i).wrapping_mul(h2)) % bits) as usize
+    }
+
+    fn contains(&self, h1: u64, h2: u64, hashes: u32) -> bool {
+        (0..hashes).all(|i| {
+            let i = self.index(h1, h2, i);
+            self.bits[i / 64] & (1 << (i % 64)) != 0
+        })
+    }
+
+    fn insert(&mut self, h1: u64, h2: u64, hashes: u32) {
+        for i in 0..hashes {
+            let i = self.index(h1, h2, i);
+            self.bits[i / 64] |= 1 << (i % 64);
+        }
+        self.len += 1;
+    }
+}
+
+/// Derives two hash values of `id` for the double hashing of the Bloom filters.
+fn hash(id: u64) -> (u64, u64) {
+    // SplitMix64. The IDs are not chosen by an adversary, so a keyed hash is unnecessary.
+    fn mix(mut z: u64) -> u64 {
+        z = z.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+    let h1 = mix(id);
+    // Make `h2` odd so that the indices do not repeat within a filter of a power-of-two size.
+    (h1, mix(h1) | 1)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use futures::executor::block_on;
+    use futures::stream::{self, TryStreamExt};
+
+    #[test]
+    fn extract_tweet_id() {
+        assert_eq!(
+            tweet_id(r#"{"created_at":"","id":1,"id_str":"1"}"#),
+            Some(1)
+        );
+        assert_eq!(tweet_id(r#"{"data":{"id":"2","text":""}}"#), Some(2));
+        assert_eq!(tweet_id(r#"{"delete":{"status":{"id":3}}}"#), None);
+        assert_eq!(tweet_id("{"), None);
+    }
+
+    #[test]
+    fn dedup_stream() {
+        let messages = vec![
+            r#"{"id":1}"#,
+            r#"{"id":2}"#,
+            r#"{"limit":{"track":1}}"#,
+            r#"{"data":{"id":"1"}}"#,
+            r#"{"limit":{"track":1}}"#,
+            r#"{"id":3}"#,
+            r#"{"id":2}"#,
+        ];
+        let stream = stream::iter(messages.into_iter().map(Ok::<_, ()>));
+
+        let mut dedup = Dedup::new(stream, Window::Count(10));
+        let output = block_on((&mut dedup).try_collect::<Vec<_>>()).unwrap();
+        assert_eq!(
+            output,
+            [
+                r#"{"id":1}"#,
+                r#"{"id":2}"#,
+                r#"{"limit":{"track":1}}"#,
+                r#"{"limit":{"track":1}}"#,
+                r#"{"id":3}"#,
+            ]
+        );
+        assert_eq!(dedup.dropped(), 2);
+    }
+
+    #[test]
+    fn exact_window() {
+        let now = Instant::now();
+        let mut exact = Exact {
+            window: Window::Count(2),
+            ids: HashSet::new(),
+            queue: VecDeque::new(),
+        };
+        assert!(exact.insert(1, now));
+        assert!(exact.insert(2, now));
+        assert!(!exact.insert(1, now));
+        assert!(exact.insert(3, now));
+        assert!(exact.insert(1, now));
+
+        let mut exact = Exact {
+            window: Window::Duration(Duration::from_secs(10)),
+            ids: HashSet::new(),
+            queue: VecDeque::new(),
+        };
+        assert!(exact.insert(1, now));
+        assert!(exact.insert(2, now + Duration::from_secs(5)));
+        assert!(!exact.insert(1, now + Duration::from_secs(9)));
+        assert!(exact.insert(1, now + Duration::from_secs(10)));
+        assert!(!exact.insert(2, now + Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn probabilistic_window() {
+        let now = Instant::now();
+        let dedup = Dedup::probabilistic(
+            stream::empty::<Result<&str, ()>>(),
+            Window::Count(1000),
+            1000,
+            0.001,
+        );
+        let mut bloom = match dedup.seen {
+            Seen::Bloom(bloom) => bloom,
+            Seen::Exact(_) => unreachable!(),
+        };
+
+        let false_positives = (0..1000).filter(|&id| !bloom.insert(id, now)).count();
+        assert!(
+            false_positives < 10,
+            "false_positives = {}",
+            false_positives
+        );
+        assert!((0..1000).all(|id| !bloom.insert(id, now)));
+
+        // The IDs are forgotten after two windows.
+        for id in 1000..3000 {
+            bloom.insert(id, now);
+        }
+        let remembered = (0..1000).filter(|&id| !bloom.insert(id, now)).count();
+        assert!(remembered < 10, "remembered = {}", remembered);
+    }
+
+    #[test]
+    fn probabilistic_duration_window() {
+        let dedup = Dedup::probabilistic(
+            stream::empty::<Result<&str, ()>>(),
+            Window::Duration(Duration::from_secs(10)),
+            1000,
+            0.001,
+        );
+        let mut bloom = match dedup.seen {
+            Seen::Bloom(bloom) => bloom,
+            Seen::Exact(_) => unreachable!(),
+        };
+        let now = bloom.current.since;
+
+        assert!(bloom.insert(1, now));
+        assert!(bloom.insert(2, now + Duration::from_secs(5)));
+        // Remembered for up to another window after the rotation.
+        assert!(!bloom.insert(1, now + Duration::from_secs(15)));
+        assert!(!bloom.insert(2, now + Duration::from_secs(19)));
+
+        // Both filters are stale after an idle period of two windows.
+        assert!(bloom.insert(3, now + Duration::from_secs(20)));
+        assert!(bloom.insert(1, now + Duration::from_secs(40)));
+        assert!(bloom.insert(3, now + Duration::from_secs(40)));
+        assert!(!bloom.insert(1, now + Duration::from_secs(41)));
+    }
+}
@@ -0,0 +1,841 @@
+//! Support for the enterprise [PowerTrack API][powertrack] (formerly Gnip).
+//!
+//! [powertrack]: https://developer.twitter.com/en/docs/tweets/filter-realtime/overview/powertrack-api
+//!
+//! Unlike the standard Streaming API, the enterprise APIs authenticate with HTTP basic
+//! authentication and the filtering rules are managed separately with the [`Rules`] API.
+//! The streams are delivered with the same framing as the standard streams,
+//! so [`Builder::listen`] yields a plain [`TwitterStream`](crate::TwitterStream).
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! use futures::prelude::*;
+//! use twitter_stream::enterprise;
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! enterprise::Builder::new("account", "prod", "username", "password")
+//!     .backfill_minutes(5)
+//!     .listen()
+//!     .try_flatten_stream()
+//!     .try_for_each(|json| {
+//!         println!("{}", json);
+//!         future::ok(())
+//!     })
+//!     .await
+//!     .unwrap();
+//! # }
+//! ```
+
+#[cfg(feature = "json")]
+pub use self::rules::{Rule, Rules, RulesError, Summary, Validation};
+
+use std::borrow::Cow;
+use std::future::Future;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use http::header::{HeaderValue, AUTHORIZATION};
+use http::{Method, Request, Uri};
+
+use crate::error::Error;
+use crate::partition::{self, Partitioned};
+use crate::service::HttpService;
+#[cfg(feature = "hyper")]
+use crate::util::https_client;
+use crate::util::{append_query, encode_path_segment};
+use crate::FutureTwitterStream;
+
+str_enum! {
+    /// The enterprise streaming product to connect to.
+    #[derive(Clone, Copy, Debug, PartialEq, Hash, Eq)]
+    pub enum Product {
+        /// The filtered PowerTrack stream (`"powertrack"`).
+        PowerTrack = "powertrack",
+        /// The 10% sampled volume stream (`"sample10"`), split into two partitions.
+        Decahose = "sample10",
+        /// The full volume stream (`"firehose"`), split into twenty partitions.
+        Firehose = "firehose",
+        /// The compliance firehose (`"compliance"`), split into eight partitions.
+        ///
+        /// The `compliance` module (requires the `json` feature) provides the typed events
+        /// of the stream.
+        Compliance = "compliance",
+    }
+}
+
+/// A builder for connections to the enterprise PowerTrack API and the volume streams.
+///
+/// The `Debug` representation of `Builder` omits the credentials.
+#[derive(Clone)]
+pub struct Builder<'a> {
+    authorization: HeaderValue,
+    account: Cow<'a, str>,
+    label: Cow<'a, str>,
+    product: Product,
+    backfill_minutes: Option<u32>,
+    partition: Option<u32>,
+    endpoint: Option<Uri>,
+}
+
+impl<'a> Builder<'a> {
+    /// Creates a builder for the stream of `label` (e.g. `"prod"`) under `account`,
+    /// authenticating with `username` and `password`.
+    ///
+    /// `account` and `label` are percent-encoded as needed to be embedded in the URIs.
+    pub fn new(
+        account: impl Into<Cow<'a, str>>,
+        label: impl Into<Cow<'a, str>>,
+        username: &str,
+        password: &str,
+    ) -> Self {
+        let credentials = base64::encode(format!("{}:{}", username, password));
+        // The Base64 alphabet only consists of the characters allowed in header values.
+        let mut authorization = HeaderValue::from_str(&format!("Basic {}", credentials))
+            .expect("Base64-encoded credentials should be a valid header value");
+        authorization.set_sensitive(true);
+
+        Builder {
+            authorization,
+            account: encode_path_segment(account.into()),
+            label: encode_path_segment(label.into()),
+            product: Product::PowerTrack,
+            backfill_minutes: None,
+            partition: None,
+            endpoint: None,
+        }
+    }
+
+    /// Set the streaming product to connect to. Defaults to [`Product::PowerTrack`].
+    ///
+    /// The partitioned streams ([`Product::Decahose`], [`Product::Firehose`] and
+    /// [`Product::Compliance`]) require a [`partition`](Builder::partition) to be specified,
+    /// or to be connected with [`listen_partitions`](Builder::listen_partitions).
+    pub fn product(&mut self, product: Product) -> &mut Self {
+        self.product = product;
+        self
+    }
+
+    /// Set the number of minutes (up to 5) of activities to be delivered on reconnection,
+    /// to recover the activities missed during a brief disconnection.
+    ///
+    /// See the [Twitter Developer Documentation][1] for more information.
+    ///
+    /// [1]: https://developer.twitter.com/en/docs/tweets/filter-realtime/guides/recovery-and-redundancy-features
+    pub fn backfill_minutes(&mut self, backfill_minutes: impl Into<Option<u32>>) -> &mut Self {
+        self.backfill_minutes = backfill_minutes.into();
+        self
+    }
+
+    /// Set the partition of the stream to connect to.
+    ///
+    /// This is required for the partitioned streams like the volume streams.
+    pub fn partition(&mut self, partition: impl Into<Option<u32>>) -> &mut Self {
+        self.partition = partition.into();
+        self
+    }
+
+    /// Set the URI of the stream to be connected.
+    ///
+    /// This overrides the default URI derived from the account name and the stream label.
+    /// The `backfillMinutes` and `partition` parameters are still appended to the URI.
+    pub fn endpoint(&mut self, endpoint: impl Into<Option<Uri>>) -> &mut Self {
+        self.endpoint = endpoint.into();
+        self
+    }
+
+    /// Start listening on the stream, returning a `Future` which resolves
+    /// to a `Stream` yielding JSON messages from the API.
+    ///
+    /// # Panics
+    ///
+    /// This will panic if the underlying HTTPS connector failed to initialize.
+    #[cfg(feature = "hyper")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "hyper")))]
+    pub fn listen(&self) -> crate::hyper::FutureTwitterStream {
+        self.listen_with_client(https_client())
+    }
+
+    /// Same as [`listen`](Builder::listen) except that it uses `client` to make HTTP request
+    /// to the endpoint.
+    ///
+    /// # Panics
+    ///
+    /// This will call `<S as Service>::call` without checking for `<S as Service>::poll_ready`
+    /// and may cause a panic if `client` is not ready to send an HTTP request yet.
+    pub fn listen_with_client<S, B>(&self, mut client: S) -> FutureTwitterStream<S::Future>
+    where
+        S: HttpService<B>,
+        B: From<Vec<u8>>,
+    {
+        let req = self.stream_request(&self.listen_uri(self.partition));
+        let response = client.call(req.map(Into::into));
+
+        FutureTwitterStream {
+            response,
+            retry: None,
+        }
+    }
+
+    /// Connects to each of `partitions` of the stream and merges them into one stream,
+    /// tagging each message with the partition it was received from.
+    ///
+    /// This ignores the partition set with [`partition`](Builder::partition).
+    ///
+    /// # Panics
+    ///
+    /// This will panic if the underlying HTTPS connector failed to initialize.
+    #[cfg(feature = "hyper")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "hyper")))]
+    pub fn listen_partitions(
+        &self,
+        partitions: &[u32],
+    ) -> impl Future<Output = Result<crate::hyper::Partitioned, crate::hyper::Error>> {
+        self.listen_partitions_with_client(partitions, https_client())
+    }
+
+    /// Same as [`listen_partitions`](Builder::listen_partitions) except that it uses `client`
+    /// to make HTTP requests to the endpoint.
+    ///
+    /// # Panics
+    ///
+    /// This will call `<S as Service>::call` without checking for `<S as Service>::poll_ready`
+    /// and may cause a panic if `client` is not ready to send an HTTP request yet.
+    pub fn listen_partitions_with_client<S, B>(
+        &self,
+        partitions: &[u32],
+        mut client: S,
+    ) -> impl Future<Output = Result<Partitioned<S::ResponseBody>, Error<S::Error>>>
+    where
+        S: HttpService<B, Response = http::Response<<S as HttpService<B>>::ResponseBody>>,
+        B: From<Vec<u8>>,
+    {
+        let partitions: Vec<_> = partitions
+            .iter()
+            .map(|&partition| {
+                let req = self.stream_request(&self.listen_uri(Some(partition)));
+                let response = client.call(req.map(Into::into));
+                let stream = FutureTwitterStream {
+                    response,
+                    retry: None,
+                };
+                (partition, stream)
+            })
+            .collect();
+        partition::connect(partitions)
+    }
+
+    /// Connects to the [Replay API][1] to recover the activities from `from` to `to`.
+    ///
+    /// The resulting stream ends after delivering all the activities in the window.
+    /// The times are truncated to minutes.
+    ///
+    /// [1]: https://developer.twitter.com/en/docs/tweets/filter-realtime/guides/recovery-and-redundancy-features
+    ///
+    /// # Panics
+    ///
+    /// This will panic if the underlying HTTPS connector failed to initialize.
+    #[cfg(feature = "hyper")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "hyper")))]
+    pub fn replay(&self, from: SystemTime, to: SystemTime) -> crate::hyper::FutureTwitterStream {
+        self.replay_with_client(from, to, https_client())
+    }
+
+    /// Same as [`replay`](Builder::replay) except that it uses `client` to make HTTP request
+    /// to the endpoint.
+    ///
+    /// # Panics
+    ///
+    /// This will call `<S as Service>::call` without checking for `<S as Service>::poll_ready`
+    /// and may cause a panic if `client` is not ready to send an HTTP request yet.
+    pub fn replay_with_client<S, B>(
+        &self,
+        from: SystemTime,
+        to: SystemTime,
+        mut client: S,
+    ) -> FutureTwitterStream<S::Future>
+    where
+        S: HttpService<B>,
+        B: From<Vec<u8>>,
+    {
+        let query = [
+            format!("fromDate={}", fmt_minutes(from)),
+            format!("toDate={}", fmt_minutes(to)),
+        ];
+        let req = self.stream_request(&append_query(self.stream_url("replay"), &query));
+        let response = client.call(req.map(Into::into));
+
+        FutureTwitterStream {
+            response,
+            retry: None,
+        }
+    }
+
+    /// Creates a client for the [Rules API][1] of the stream.
+    ///
+    /// [1]: https://developer.twitter.com/en/docs/tweets/filter-realtime/api-reference/powertrack-rules
+    ///
+    /// # Panics
+    ///
+    /// This will panic if the underlying HTTPS connector failed to initialize.
+    #[cfg(all(feature = "hyper", feature = "json"))]
+    #[cfg_attr(docsrs, doc(cfg(all(feature = "hyper", feature = "json"))))]
+    pub fn rules(&self) -> Rules<crate::hyper::Client> {
+        self.rules_with_client(https_client())
+    }
+
+    /// Same as [`rules`](Builder::rules) except that the `Rules` uses `client` to make HTTP
+    /// requests to the endpoint.
+    #[cfg(feature = "json")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+    pub fn rules_with_client<S>(&self, client: S) -> Rules<S> {
+        let base = format!(
+            "https://gnip-api.twitter.com/rules/powertrack/accounts/{}/publishers/twitter/{}",
+            self.account, self.label,
+        );
+        Rules::new(self.authorization.clone(), base, client)
+    }
+
+    fn stream_url(&self, kind: &str) -> String {
+        format!(
+            "https://gnip-stream.twitter.com/{}/{}/accounts/{}/publishers/twitter/{}.json",
+            kind,
+            self.product.as_ref(),
+            self.account,
+            self.label,
+        )
+    }
+
+    fn listen_uri(&self, partition: Option<u32>) -> String {
+        let base = self.endpoint.as_ref().map_or_else(
+            || self.stream_url("stream"),
+            |endpoint| endpoint.to_string(),
+        );
+        let mut query = Vec::new();
+        if let Some(backfill_minutes) = self.backfill_minutes {
+            query.push(format!("backfillMinutes={}", backfill_minutes));
+        }
+        if let Some(partition) = partition {
+            query.push(format!("partition={}", partition));
+        }
+        append_query(base, &query)
+    }
+
+    fn stream_request(&self, uri: &str) -> Request<Vec<u8>> {
+        let req = Request::builder()
+            .method(Method::GET)
+            .uri(uri)
+            .header(AUTHORIZATION, self.authorization.clone());
+
+        #[cfg(feature = "gzip")]
+        let req = req.header(
+            http::header::ACCEPT_ENCODING,
+            HeaderValue::from_static("gzip"),
+        );
+
+        req.body(Vec::new()).unwrap()
+    }
+}
+
+impl<'a> std::fmt::Debug for Builder<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Builder")
+            .field("authorization", &format_args!("<hidden>"))
+            .field("account", &self.account)
+            .field("label", &self.label)
+            .field("product", &self.product)
+            .field("backfill_minutes", &self.backfill_minutes)
+            .field("partition", &self.partition)
+            .field("endpoint", &self.endpoint)
+            .finish()
+    }
+}
+
+/// Formats `t` in the `YYYYMMDDhhmm` format (in UTC) used by the Replay API.
+fn fmt_minutes(t: SystemTime) -> String {
+    let secs = t
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let (days, secs) = (secs / 86400, secs % 86400);
+
+    // Converts the days since the UNIX epoch to a civil date.
+    // <http://howardhinnant.github.io/date_algorithms.html#civil_from_days>
+    let z = days as i64 + 719_468;
+    let era = z / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}{:02}{:02}{:02}{:02}",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs % 3600 / 60
+    )
+}
+
+#[cfg(feature = "json")]
+mod rules {
+    use std::error;
+    use std::fmt::{self, Display, Formatter};
+    use std::future::Future;
+
+    use http::header::{HeaderValue, AUTHORIZATION, CONTENT_TYPE};
+    use http::{Method, Request, StatusCode};
+    use http_body::Body;
+    use serde::{Deserialize, Serialize};
+
+    use crate::error::Error;
+    use crate::service::HttpService;
+    use crate::util::collect_body;
+
+    /// A client for the PowerTrack Rules API, created by [`Builder::rules`](super::Builder::rules).
+    #[derive(Clone)]
+    pub struct Rules<S> {
+        authorization: HeaderValue,
+        base: String,
+        client: S,
+    }
+
+    /// A PowerTrack rule.
+    #[derive(Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+    pub struct Rule {
+        /// The rule text.
+        pub value: String,
+        /// An optional tag to be included in the `matching_rules` of the matched activities.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub tag: Option<String>,
+        /// The ID of the rule, assigned by the server.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub id: Option<u64>,
+    }
+
+    /// The summary of an add, delete or validation request.
+    ///
+    /// Only the fields relevant to the request are non-zero.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Deserialize)]
+    #[serde(default)]
+    pub struct Summary {
+        /// The number of rules created.
+        pub created: u64,
+        /// The number of rules not created.
+        pub not_created: u64,
+        /// The number of rules deleted.
+        pub deleted: u64,
+        /// The number of rules not deleted.
+        pub not_deleted: u64,
+        /// The number of valid rules.
+        pub valid: u64,
+        /// The number of invalid rules.
+        pub not_valid: u64,
+    }
+
+    /// The validation result of a rule.
+    #[derive(Clone, Debug, PartialEq, Eq, Hash, Deserialize)]
+    pub struct Validation {
+        /// The validated rule.
+        pub rule: Rule,
+        /// Whether the rule is valid.
+        pub is_valid: bool,
+        /// The reason why the rule is invalid.
+        #[serde(default)]
+        pub message: Option<String>,
+    }
+
+    /// An error occurred while calling the Rules API.
+    #[derive(Debug)]
+    pub enum RulesError<E = Box<dyn error::Error + Send + Sync>> {
+        /// An error occurred while sending the request or receiving the response.
+        Request(Error<E>),
+        /// The server rejected the request with the given status code and error message.
+        Api(StatusCode, String),
+        /// The server returned a malformed response.
+        Json(serde_json::Error),
+    }
+
+    #[derive(Serialize)]
+    struct RulesBody<'a> {
+        rules: &'a [Rule],
+    }
+
+    #[derive(Deserialize)]
+    struct ListResponse {
+        rules: Vec<Rule>,
+    }
+
+    #[derive(Deserialize)]
+    struct SummaryResponse {
+        summary: Summary,
+    }
+
+    #[derive(Deserialize)]
+    struct ValidationResponse {
+        detail: Vec<Validation>,
+    }
+
+    impl<S> Rules<S> {
+        pub(super) fn new(authorization: HeaderValue, base: String, client: S) -> Self {
+            Rules {
+                authorization,
+                base,
+                client,
+            }
+        }
+    }
+
+    impl<S> Rules<S> {
+        /// Lists all the rules of the stream.
+        pub fn list<B>(&mut self) -> impl Future<Output = Result<Vec<Rule>, RulesError<S::Error>>>
+        where
+            S: HttpService<B, Response = http::Response<<S as HttpService<B>>::ResponseBody>>,
+            <S::ResponseBody as Body>::Error: Into<S::Error>,
+            B: From<Vec<u8>>,
+        {
+            let res = self.send(Method::GET, ".json", None);
+            async move { Ok(res.await.and_then(parse::<ListResponse, _>)?.rules) }
+        }
+
+        /// Adds `rules` to the stream.
+        pub fn add<B>(
+            &mut self,
+            rules: &[Rule],
+        ) -> impl Future<Output = Result<Summary, RulesError<S::Error>>>
+        where
+            S: HttpService<B, Response = http::Response<<S as HttpService<B>>::ResponseBody>>,
+            <S::ResponseBody as Body>::Error: Into<S::Error>,
+            B: From<Vec<u8>>,
+        {
+            let res = self.send(Method::POST, ".json", Some(rules));
+            async move { Ok(res.await.and_then(parse::<SummaryResponse, _>)?.summary) }
+        }
+
+        /// Deletes `rules` from the stream.
+        ///
+        /// The rules are matched by their `value`s.
+        pub fn delete<B>(
+            &mut self,
+            rules: &[Rule],
+        ) -> impl Future<Output = Result<Summary, RulesError<S::Error>>>
+        where
+            S: HttpService<B, Response = http::Response<<S as HttpService<B>>::ResponseBody>>,
+            <S::ResponseBody as Body>::Error: Into<S::Error>,
+            B: From<Vec<u8>>,
+        {
+            let res = self.send(Method::POST, ".json?_method=delete", Some(rules));
+            async move { Ok(res.await.and_then(parse::<SummaryResponse, _>)?.summary) }
+        }
+
+        /// Validates `rules` without adding them to the stream.
+        pub fn validate<B>(
+            &mut self,
+            rules: &[Rule],
+        ) -> impl Future<Output = Result<Vec<Validation>, RulesError<S::Error>>>
+        where
+            S: HttpService<B, Response = http::Response<<S as HttpService<B>>::ResponseBody>>,
+            <S::ResponseBody as Body>::Error: Into<S::Error>,
+            B: From<Vec<u8>>,
+        {
+            let res = self.send(Method::POST, "/validation.json", Some(rules));
+            async move { Ok(res.await.and_then(parse::<ValidationResponse, _>)?.detail) }
+        }
+
+        fn send<B>(
+            &mut self,
+            method: Method,
+            path: &str,
+            rules: Option<&[Rule]>,
+        ) -> impl Future<Output = Result<Vec<u8>, RulesError<S::Error>>>
+        where
+            S: HttpService<B, Response = http::Response<<S as HttpService<B>>::ResponseBody>>,
+            <S::ResponseBody as Body>::Error: Into<S::Error>,
+            B: From<Vec<u8>>,
+        {
+            let req = Request::builder()
+                .method(method)
+                .uri(format!("{}{}", self.base, path))
+                .header(AUTHORIZATION, self.authorization.clone());
+            let req = if let Some(rules) = rules {
+                let body = serde_json::to_vec(&RulesBody { rules }).unwrap();
+                req.header(CONTENT_TYPE, HeaderValue::from_static("application/json"))
+                    .body(body)
+            } else {
+                req.body(Vec::new())
+            };
+            let res = self.client.call(req.unwrap().map(Into::into));
+
+            async move {
+                let res = res
+                    .await
+                    .map_err(|e| RulesError::Request(Error::Service(e)))?;
+                let status = res.status();
+                let body = collect_body(res.into_body())
+                    .await
+                    .map_err(|e| RulesError::Request(e.map_service(Into::into)))?;
+                if !status.is_success() {
+                    let message = String::from_utf8_lossy(&body).into_owned();
+                    return Err(RulesError::Api(status, message));
+                }
+                Ok(body)
+            }
+        }
+    }
+
+    impl<S> std::fmt::Debug for Rules<S> {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.debug_struct("Rules")
+                .field("authorization", &format_args!("<hidden>"))
+                .field("base", &self.base)
+                .finish()
+        }
+    }
+
+    impl<E: error::Error + 'static> error::Error for RulesError<E> {
+        fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+            match *self {
+                RulesError::Request(ref e) => Some(e),
+                RulesError::Api(..) => None,
+                RulesError::Json(ref e) => Some(e),
+            }
+        }
+    }
+
+    impl<E: Display> Display for RulesError<E> {
+        fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+            match *self {
+                RulesError::Request(ref e) => Display::fmt(e, f),
+                RulesError::Api(status, ref message) => write!(f, "{}: {}", status, message),
+                RulesError::Json(ref e) => write!(f, "JSON error: {}", e),
+            }
+        }
+    }
+
+    fn parse<T: serde::de::DeserializeOwned, E>(body: Vec<u8>) -> Result<T, RulesError<E>> {
+        serde_json::from_slice(&body).map_err(RulesError::Json)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::time::Duration;
+
+    #[test]
+    fn fmt_replay_date() {
+        assert_eq!(fmt_minutes(UNIX_EPOCH), "197001010000");
+        let t = UNIX_EPOCH + Duration::from_secs(1_582_977_659); // 2020-02-29T12:00:59Z
+        assert_eq!(fmt_minutes(t), "202002291200");
+    }
+
+    #[test]
+    fn stream_uri() {
+        let mut builder = Builder::new("acme", "prod", "user", "pass");
+        builder.backfill_minutes(5).partition(1);
+
+        let client = tower::service_fn(|req: http::Request<Vec<u8>>| {
+            assert_eq!(
+                req.uri(),
+                "https://gnip-stream.twitter.com/stream/powertrack/accounts/acme/publishers/\
+                 twitter/prod.json?backfillMinutes=5&partition=1"
+            );
+            assert_eq!(req.headers()[AUTHORIZATION], "Basic dXNlcjpwYXNz");
+            futures::future::ok::<_, hyper_pkg::Error>(
+                http::Response::new(hyper_pkg::Body::empty()),
+            )
+        });
+
+        futures::executor::block_on(builder.listen_with_client(client)).unwrap();
+    }
+
+    #[test]
+    fn encode_account_and_label() {
+        let builder = Builder::new("acme corp", "prod/1", "user", "pass");
+        let client = tower::service_fn(|req: http::Request<Vec<u8>>| {
+            assert_eq!(
+                req.uri(),
+                "https://gnip-stream.twitter.com/stream/powertrack/accounts/acme%20corp/\
+                 publishers/twitter/prod%2F1.json"
+            );
+            futures::future::ok::<_, hyper_pkg::Error>(
+                http::Response::new(hyper_pkg::Body::empty()),
+            )
+        });
+
+        futures::executor::block_on(builder.listen_with_client(client)).unwrap();
+    }
+
+    #[test]
+    fn replay_uri() {
+        let builder = Builder::new("acme", "prod", "user", "pass");
+        let client = tower::service_fn(|req: http::Request<Vec<u8>>| {
+            assert_eq!(req.method(), Method::GET);
+            assert_eq!(
+                req.uri(),
+                "https://gnip-stream.twitter.com/replay/powertrack/accounts/acme/publishers/\
+                 twitter/prod.json?fromDate=202002291200&toDate=202002291230"
+            );
+            assert_eq!(req.headers()[AUTHORIZATION], "Basic dXNlcjpwYXNz");
+            futures::future::ok::<_, hyper_pkg::Error>(
+                http::Response::new(hyper_pkg::Body::empty()),
+            )
+        });
+
+        let from = UNIX_EPOCH + Duration::from_secs(1_582_977_659);
+        let to = from + Duration::from_secs(30 * 60);
+        futures::executor::block_on(builder.replay_with_client(from, to, client)).unwrap();
+    }
+
+    #[cfg(feature = "json")]
+    fn rules_client(
+        method: Method,
+        uri: &'static str,
+        body: &'static str,
+        status: http::StatusCode,
+        response: &'static str,
+    ) -> impl HttpService<
+        Vec<u8>,
+        Response = http::Response<hyper_pkg::Body>,
+        Error = hyper_pkg::Error,
+        ResponseBody = hyper_pkg::Body,
+    > {
+        const BASE: &str =
+            "https://gnip-api.twitter.com/rules/powertrack/accounts/acme/publishers/twitter/prod";
+
+        tower::service_fn(move |req: http::Request<Vec<u8>>| {
+            assert_eq!(req.method(), method);
+            assert_eq!(req.uri(), &*format!("{}{}", BASE, uri));
+            assert_eq!(req.headers()[AUTHORIZATION], "Basic dXNlcjpwYXNz");
+            assert_eq!(std::str::from_utf8(req.body()).unwrap(), body);
+            let res = http::Response::builder()
+                .status(status)
+                .body(hyper_pkg::Body::from(response))
+                .unwrap();
+            futures::future::ok::<_, hyper_pkg::Error>(res)
+        })
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn rules() {
+        use futures::executor::block_on;
+        use http::StatusCode;
+
+        let builder = Builder::new("acme", "prod", "user", "pass");
+        let rules = [Rule {
+            value: "cat has:media".to_owned(),
+            tag: Some("cats".to_owned()),
+            id: None,
+        }];
+        let rules_json = r#"{"rules":[{"value":"cat has:media","tag":"cats"}]}"#;
+
+        let client = rules_client(
+            Method::GET,
+            ".json",
+            "",
+            StatusCode::OK,
+            r#"{"rules":[{"value":"cat has:media","tag":"cats","id":1234}]}"#,
+        );
+        let list = block_on(builder.rules_with_client(client).list()).unwrap();
+        assert_eq!(
+            list,
+            [Rule {
+                id: Some(1234),
+                ..rules[0].clone()
+            }]
+        );
+
+        let client = rules_client(
+            Method::POST,
+            ".json",
+            rules_json,
+            StatusCode::CREATED,
+            r#"{"summary":{"created":1,"not_created":0},"detail":[]}"#,
+        );
+        let summary = block_on(builder.rules_with_client(client).add(&rules)).unwrap();
+        assert_eq!(
+            summary,
+            Summary {
+                created: 1,
+                ..Summary::default()
+            }
+        );
+
+        let client = rules_client(
+            Method::POST,
+            ".json?_method=delete",
+            rules_json,
+            StatusCode::OK,
+            r#"{"summary":{"deleted":1,"not_deleted":0}}"#,
+        );
+        let summary = block_on(builder.rules_with_client(client).delete(&rules)).unwrap();
+        assert_eq!(
+            summary,
+            Summary {
+                deleted: 1,
+                ..Summary::default()
+            }
+        );
+
+        let client = rules_client(
+            Method::POST,
+            "/validation.json",
+            rules_json,
+            StatusCode::OK,
+            r#"{
+                "summary":{"valid":0,"not_valid":1},
+                "detail":[{
+                    "rule":{"value":"cat has:media","tag":"cats"},
+                    "is_valid":false,
+                    "message":"Unknown operator"
+                }]
+            }"#,
+        );
+        let validations = block_on(builder.rules_with_client(client).validate(&rules)).unwrap();
+        assert_eq!(
+            validations,
+            [Validation {
+                rule: rules[0].clone(),
+                is_valid: false,
+                message: Some("Unknown operator".to_owned()),
+            }]
+        );
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn rules_error() {
+        use futures::executor::block_on;
+        use http::StatusCode;
+
+        let builder = Builder::new("acme", "prod", "user", "pass");
+        let rules = [Rule::default()];
+
+        let error = r#"{"error":{"message":"Rule cannot be empty"}}"#;
+        let client = rules_client(
+            Method::POST,
+            ".json",
+            r#"{"rules":[{"value":""}]}"#,
+            StatusCode::UNPROCESSABLE_ENTITY,
+            error,
+        );
+        match block_on(builder.rules_with_client(client).add(&rules)) {
+            Err(RulesError::Api(StatusCode::UNPROCESSABLE_ENTITY, ref message)) => {
+                assert_eq!(message, error)
+            }
+            result => panic!("unexpected result: {:?}", result),
+        }
+
+        let client = rules_client(Method::GET, ".json", "", StatusCode::OK, "{}");
+        match block_on(builder.rules_with_client(client).list()) {
+            Err(RulesError::Json(_)) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+}
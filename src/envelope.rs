@@ -0,0 +1,158 @@
+//! Messages annotated with the metadata of their reception.
+//!
+//! [`TwitterStream::with_metadata`](crate::TwitterStream::with_metadata) turns a stream into
+//! a [`WithMetadata`] stream, which yields each JSON message in an [`Envelope`] recording
+//! when the message was received and where it came from. This is useful for measuring
+//! the latency of the messages and ordering the messages from several connections.
+
+use std::fmt::{self, Display, Formatter};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::task::{Context, Poll};
+use std::time::{Instant, SystemTime};
+
+use bytes::Bytes;
+use futures_core::Stream;
+use futures_util::ready;
+use http_body::Body;
+use pin_project_lite::pin_project;
+
+use crate::error::Error;
+use crate::TwitterStream;
+
+pin_project! {
+    /// A stream yielding [`Envelope`]s, created by
+    /// [`TwitterStream::with_metadata`](crate::TwitterStream::with_metadata).
+    pub struct WithMetadata<B: Body> {
+        #[pin]
+        inner: TwitterStream<B>,
+        connection_id: ConnectionId,
+        sequence: u64,
+    }
+}
+
+/// A JSON message along with the metadata of its reception.
+#[derive(Clone, Debug)]
+pub struct Envelope {
+    /// The JSON message.
+    pub json: string::String<Bytes>,
+    /// The time the message was received, on the monotonic clock.
+    pub received_at: Instant,
+    /// The time the message was received, on the system clock.
+    ///
+    /// Unlike `received_at`, this can be compared with the timestamps in the messages,
+    /// but may jump if the system clock is adjusted.
+    pub received_at_system: SystemTime,
+    /// The sequence number of the message in the connection, starting from `0`.
+    ///
+    /// The keep-alive signals are not counted.
+    pub sequence: u64,
+    /// The byte offset of the message in the (decompressed) response body of the connection.
+    pub offset: u64,
+    /// The identifier of the connection the message was received from.
+    pub connection_id: ConnectionId,
+}
+
+/// An identifier of a connection to the Streaming API.
+///
+/// The identifiers are unique in the process, and increase in the order of the calls to
+/// [`TwitterStream::with_metadata`](crate::TwitterStream::with_metadata).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ConnectionId(u64);
+
+impl<B: Body> WithMetadata<B> {
+    pub(crate) fn new(inner: TwitterStream<B>) -> Self {
+        WithMetadata {
+            inner,
+            connection_id: ConnectionId::next(),
+            sequence: 0,
+        }
+    }
+
+    /// Returns the identifier of the connection.
+    pub fn connection_id(&self) -> ConnectionId {
+        self.connection_id
+    }
+
+    /// Consumes the `WithMetadata`, returning the underlying stream.
+    pub fn into_inner(self) -> TwitterStream<B> {
+        self.inner
+    }
+}
+
+impl<B: Body> Stream for WithMetadata<B> {
+    type Item = Result<Envelope, Error<B::Error>>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+
+        let (json, offset) = match ready!(this.inner.poll_next_message(cx)?) {
+            Some(t) => t,
+            None => return Poll::Ready(None),
+        };
+
+        let envelope = Envelope {
+            json,
+            received_at: Instant::now(),
+            received_at_system: SystemTime::now(),
+            sequence: *this.sequence,
+            offset,
+            connection_id: *this.connection_id,
+        };
+        *this.sequence += 1;
+
+        Poll::Ready(Some(Ok(envelope)))
+    }
+}
+
+impl ConnectionId {
+    pub(crate) fn next() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        ConnectionId(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+
+    /// Returns the identifier as an integer.
+    pub fn as_u64(self) -> u64 {
+        self.0
+    }
+}
+
+impl Display for ConnectionId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Display::fmt(&self.0, f)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use futures::executor::block_on;
+    use futures::TryStreamExt;
+
+    use crate::util::test::stream_from_body;
+
+    #[test]
+    fn metadata() {
+        let body = b"{\"a\":1}\r\n\r\n{\"b\":2}\r\n{}\r\n";
+        let connect = || stream_from_body(body).with_metadata();
+
+        let first = connect();
+        let second = connect();
+        assert!(first.connection_id() < second.connection_id());
+
+        let id = first.connection_id();
+        let envelopes: Vec<_> = block_on(first.try_collect()).unwrap();
+        let metadata: Vec<_> = envelopes
+            .iter()
+            .map(|e| (&*e.json, e.sequence, e.offset, e.connection_id))
+            .collect();
+        assert_eq!(
+            metadata,
+            [
+                ("{\"a\":1}", 0, 0, id),
+                ("{\"b\":2}", 1, 11, id),
+                ("{}", 2, 20, id),
+            ]
+        );
+        assert!(envelopes[0].received_at <= envelopes[2].received_at);
+    }
+}
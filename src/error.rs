@@ -20,6 +20,23 @@ pub enum Error<E = Box<dyn error::Error + Send + Sync>> {
     Utf8(Utf8Error),
 }
 
+impl<E> Error<E> {
+    /// Maps the `Service` variant of `self` with `f`.
+    pub fn map_service<F, E2>(self, f: F) -> Error<E2>
+    where
+        F: FnOnce(E) -> E2,
+    {
+        use crate::Error::*;
+
+        match self {
+            Gzip(e) => Gzip(e),
+            Http(code) => Http(code),
+            Service(e) => Service(f(e)),
+            Utf8(e) => Utf8(e),
+        }
+    }
+}
+
 impl<E: error::Error + 'static> error::Error for Error<E> {
     fn source(&self) -> Option<&(dyn error::Error + 'static)> {
         use crate::Error::*;
@@ -1,5 +1,8 @@
 //! Type aliases for use with [`hyper`](hyper_pkg) crate's HTTP client.
 
+/// A type alias of the HTTPS client used by the `listen`-like methods.
+pub type Client = hyper_pkg::Client<hyper_tls::HttpsConnector<hyper_pkg::client::HttpConnector>>;
+
 /// A type alias of [`FutureTwitterStream`](crate::FutureTwitterStream) using Hyper's HTTP client.
 pub type FutureTwitterStream = crate::FutureTwitterStream<hyper_pkg::client::ResponseFuture>;
 /// A type alias of [`Error`](crate::error::Error)
@@ -7,3 +10,17 @@ pub type FutureTwitterStream = crate::FutureTwitterStream<hyper_pkg::client::Res
 pub type Error = crate::Error<hyper_pkg::Error>;
 /// A type alias of [`TwitterStream`](crate::TwitterStream) using Hyper's HTTP client.
 pub type TwitterStream = crate::TwitterStream<hyper_pkg::Body>;
+/// A type alias of [`Partitioned`](crate::partition::Partitioned) using Hyper's HTTP client.
+pub type Partitioned = crate::partition::Partitioned<hyper_pkg::Body>;
+/// A type alias of [`FutureReadAhead`](crate::read_ahead::FutureReadAhead) using Hyper's HTTP
+/// client.
+pub type FutureReadAhead = crate::read_ahead::FutureReadAhead<hyper_pkg::client::ResponseFuture>;
+/// A type alias of [`ReadAhead`](crate::read_ahead::ReadAhead) using Hyper's HTTP client.
+pub type ReadAhead = crate::read_ahead::ReadAhead<hyper_pkg::Body>;
+/// A type alias of [`Driver`](crate::read_ahead::Driver) using Hyper's HTTP client.
+pub type Driver = crate::read_ahead::Driver<hyper_pkg::Body>;
+/// A type alias of [`Multiplexer`](crate::multiplex::Multiplexer) using Hyper's HTTP client.
+#[cfg(feature = "multiplex")]
+#[cfg_attr(docsrs, doc(cfg(feature = "multiplex")))]
+pub type Multiplexer<'a, C = String, A = String> =
+    crate::multiplex::Multiplexer<'a, Client, hyper_pkg::Body, C, A>;
@@ -0,0 +1,100 @@
+//! A mode of `TwitterStream` surfacing the keep-alive signals.
+//!
+//! The Streaming API sends a blank line every 30 seconds as a keep-alive signal, which
+//! [`TwitterStream`](crate::TwitterStream) discards by default.
+//! [`TwitterStream::with_keep_alive`](crate::TwitterStream::with_keep_alive) turns a stream into
+//! a [`WithKeepAlive`] stream, which yields the signals as [`Frame::KeepAlive`], so that you can
+//! implement your own liveness checks on the stream.
+
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use bytes::Bytes;
+use futures_core::Stream;
+use futures_util::ready;
+use http_body::Body;
+use pin_project_lite::pin_project;
+
+use crate::error::Error;
+use crate::TwitterStream;
+
+pin_project! {
+    /// A stream yielding [`Frame`]s, created by
+    /// [`TwitterStream::with_keep_alive`](crate::TwitterStream::with_keep_alive).
+    pub struct WithKeepAlive<B: Body> {
+        #[pin]
+        inner: TwitterStream<B>,
+    }
+}
+
+/// An item of [`WithKeepAlive`].
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Frame {
+    /// A JSON message.
+    Message(string::String<Bytes>),
+    /// A keep-alive signal (a blank line).
+    KeepAlive,
+}
+
+impl<B: Body> WithKeepAlive<B> {
+    pub(crate) fn new(inner: TwitterStream<B>) -> Self {
+        WithKeepAlive { inner }
+    }
+
+    /// Consumes the `WithKeepAlive`, returning the underlying stream.
+    pub fn into_inner(self) -> TwitterStream<B> {
+        self.inner
+    }
+}
+
+impl<B: Body> Stream for WithKeepAlive<B> {
+    type Item = Result<Frame, Error<B::Error>>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let frame = match ready!(self.project().inner.poll_next_frame(cx)?) {
+            Some((Some(json), _)) => Frame::Message(json),
+            Some((None, _)) => Frame::KeepAlive,
+            None => return Poll::Ready(None),
+        };
+
+        Poll::Ready(Some(Ok(frame)))
+    }
+}
+
+impl Frame {
+    /// Returns the JSON message if the frame is a `Message`.
+    pub fn into_message(self) -> Option<string::String<Bytes>> {
+        match self {
+            Frame::Message(json) => Some(json),
+            Frame::KeepAlive => None,
+        }
+    }
+
+    /// Returns `true` if the frame is a `KeepAlive`.
+    pub fn is_keep_alive(&self) -> bool {
+        match *self {
+            Frame::Message(_) => false,
+            Frame::KeepAlive => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use futures::executor::block_on;
+    use futures::TryStreamExt;
+
+    use crate::util::test::stream_from_body;
+
+    #[test]
+    fn keep_alive() {
+        let stream = stream_from_body(b"\r\n{\"a\":1}\r\n\r\n\r\n{}\r\n").with_keep_alive();
+
+        let frames: Vec<Frame> = block_on(stream.try_collect()).unwrap();
+        let frames: Vec<_> = frames.into_iter().map(Frame::into_message).collect();
+        let frames: Vec<_> = frames.iter().map(|json| json.as_deref()).collect();
+        assert_eq!(frames, [None, Some("{\"a\":1}"), None, None, Some("{}")]);
+    }
+}
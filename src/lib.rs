@@ -51,7 +51,10 @@ but `TwitterStream` discards it so that you can always expect to yield a valid J
 On the other hand, this means that you cannot use the blank line to set a timeout on `Stream`-level.
 If you want the stream to time out on network stalls, set a timeout on the underlying
 HTTP connector, instead of the `Stream` (see the [`timeout` example] in the crate's repository
-for details).
+for details), or use [`TwitterStream::with_keep_alive`] to receive the keep-alive signals.
+
+Each line is validated as UTF-8 before being yielded. At high message rates, enable the `simd`
+feature to validate the lines with vectorized instructions.
 
 [stalls]: https://developer.twitter.com/en/docs/tweets/filter-realtime/guides/connecting#stalls
 [`timeout` example]: https://github.com/tesaguri/twitter-stream-rs/blob/v0.10.0-alpha.6/examples/timeout.rs
@@ -94,15 +97,49 @@ messages.
 #[macro_use]
 mod util;
 
+pub mod auth;
+pub mod broadcast;
 pub mod builder;
+#[cfg(feature = "codec")]
+#[cfg_attr(docsrs, doc(cfg(feature = "codec")))]
+pub mod codec;
+#[cfg(feature = "json")]
+#[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+pub mod compliance;
+pub mod dedup;
+pub mod enterprise;
+pub mod envelope;
 pub mod error;
 #[cfg(feature = "hyper")]
 #[cfg_attr(docsrs, doc(cfg(feature = "hyper")))]
 pub mod hyper;
+pub mod keep_alive;
+pub mod limit;
+#[cfg(feature = "multiplex")]
+#[cfg_attr(docsrs, doc(cfg(feature = "multiplex")))]
+pub mod multiplex;
+pub mod partition;
+pub mod raw;
+pub mod read_ahead;
+#[cfg(feature = "json")]
+#[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+pub mod resolve;
+pub mod rest;
+pub mod scan;
 pub mod service;
-
+pub mod snowflake;
+pub mod token;
+pub mod utf8;
+pub mod v2;
+#[cfg(feature = "webhook")]
+#[cfg_attr(docsrs, doc(cfg(feature = "webhook")))]
+pub mod webhook;
+
+#[cfg(not(feature = "codec"))]
+#[allow(dead_code)]
+mod codec;
 mod gzip;
-mod token;
+mod validate;
 
 pub use oauth::Credentials;
 
@@ -133,14 +170,26 @@ pin_project! {
     pub struct FutureTwitterStream<F> {
         #[pin]
         response: F,
+        // Re-sends the request with the given timestamp offset.
+        retry: Option<Box<dyn FnOnce(i64) -> F + Send + Sync>>,
     }
 }
 
+// The retry closure must not make the future `!Sync` where `F` is `Sync`.
+static_assertions::assert_impl_all!(
+    FutureTwitterStream<futures_util::future::Pending<()>>: Send, Sync
+);
+
 pin_project! {
     /// A listener for Twitter Streaming API, yielding JSON strings returned from the API.
     pub struct TwitterStream<B: Body> {
         #[pin]
         inner: Lines<MaybeGzip<HttpBodyAsStream<B>>>,
+        // Byte offset of the next line in the (decompressed) response body.
+        offset: u64,
+        utf8_policy: utf8::Policy,
+        skipped_lines: u64,
+        invalid_lines: Option<futures_channel::mpsc::UnboundedSender<utf8::InvalidLine>>,
     }
 }
 
@@ -153,6 +202,164 @@ impl<B: Body> TwitterStream<B> {
     {
         Builder::new(token)
     }
+
+    /// Set the policy on the lines that are not valid as UTF-8.
+    ///
+    /// Defaults to [`Policy::Error`](utf8::Policy::Error).
+    pub fn utf8_policy(&mut self, policy: utf8::Policy) -> &mut Self {
+        self.utf8_policy = policy;
+        self
+    }
+
+    /// Returns the number of lines skipped under [`Policy::Skip`](utf8::Policy::Skip).
+    pub fn skipped_lines(&self) -> u64 {
+        self.skipped_lines
+    }
+
+    /// Returns a stream yielding the lines skipped under [`Policy::Skip`](utf8::Policy::Skip)
+    /// from now on.
+    ///
+    /// Only the most recently returned `InvalidLines` receives the lines.
+    pub fn invalid_lines(&mut self) -> utf8::InvalidLines {
+        let (tx, rx) = utf8::InvalidLines::new();
+        self.invalid_lines = Some(tx);
+        rx
+    }
+
+    /// Turns the stream into one yielding [`Envelope`](envelope::Envelope)s, which carry
+    /// the metadata of the messages like the time they were received, in addition to the JSON
+    /// strings.
+    ///
+    /// Each call assigns a new [`ConnectionId`](envelope::ConnectionId) to the stream, so
+    /// the messages from a reconnected stream can be told apart from the previous ones.
+    pub fn with_metadata(self) -> envelope::WithMetadata<B> {
+        envelope::WithMetadata::new(self)
+    }
+
+    /// Turns the stream into one yielding the keep-alive signals as
+    /// [`Frame::KeepAlive`](keep_alive::Frame::KeepAlive), in addition to the JSON messages.
+    pub fn with_keep_alive(self) -> keep_alive::WithKeepAlive<B> {
+        keep_alive::WithKeepAlive::new(self)
+    }
+
+    /// Turns the stream into a [`Hub`](broadcast::Hub) delivering the messages to several
+    /// subscribers.
+    pub fn broadcast(self) -> broadcast::Hub<B> {
+        broadcast::Hub::new(self)
+    }
+
+    /// Splits the stream into a [`ReadAhead`](read_ahead::ReadAhead) stream and
+    /// a [`Driver`](read_ahead::Driver) reading the response ahead into a queue of `capacity`
+    /// messages.
+    ///
+    /// Enable the [`stall_warnings`](Builder::stall_warnings) parameter to receive
+    /// the `FALLING_BEHIND` warnings as
+    /// [`Event::FallingBehind`](read_ahead::Event::FallingBehind).
+    ///
+    /// # Panics
+    ///
+    /// This will panic if `capacity` is zero.
+    pub fn read_ahead(self, capacity: usize) -> (read_ahead::ReadAhead<B>, read_ahead::Driver<B>) {
+        read_ahead::ReadAhead::new(self, capacity)
+    }
+
+    /// Turns the stream into one yielding the messages as raw `Bytes`, without validating them
+    /// as UTF-8.
+    ///
+    /// The UTF-8 policy set with [`utf8_policy`](TwitterStream::utf8_policy) has no effect on
+    /// the returned stream.
+    pub fn into_raw(self) -> raw::RawStream<B> {
+        raw::RawStream::new(self)
+    }
+
+    /// Polls the next line of the response body, including the blank lines, along with
+    /// its byte offset in the body.
+    #[allow(clippy::type_complexity)]
+    fn poll_next_line(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<(Bytes, u64), Error<B::Error>>>> {
+        let this = self.project();
+        let line = match ready!(this.inner.poll_next(cx)?) {
+            Some(line) => line,
+            None => return Poll::Ready(None),
+        };
+        let offset = *this.offset;
+        // The length of the line plus the CRLF.
+        *this.offset += line.len() as u64 + 2;
+        Poll::Ready(Some(Ok((line, offset))))
+    }
+
+    /// Polls the next line of the response body as a JSON string, or `None` if the line is
+    /// a keep-alive signal, along with its byte offset in the body.
+    ///
+    /// The lines that are not valid as UTF-8 are handled according to the `utf8_policy`.
+    #[allow(clippy::type_complexity)]
+    fn poll_next_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<(Option<string::String<Bytes>>, u64), Error<B::Error>>>> {
+        loop {
+            let (line, offset) = match ready!(self.as_mut().poll_next_line(cx)?) {
+                Some(t) => t,
+                None => return Poll::Ready(None),
+            };
+
+            let e = match validate::validate_line(&line) {
+                Ok(true) => return Poll::Ready(Some(Ok((None, offset)))),
+                Ok(false) => {
+                    let json = unsafe {
+                        // Safety:
+                        // - We have checked above that `line` is valid as UTF-8.
+                        // - `Bytes` satisfies the requirements of `string::StableAsRef` trait
+                        // (https://github.com/carllerche/string/pull/17)
+                        string::String::<Bytes>::from_utf8_unchecked(line)
+                    };
+                    return Poll::Ready(Some(Ok((Some(json), offset))));
+                }
+                Err(e) => e,
+            };
+
+            let this = self.as_mut().project();
+            match *this.utf8_policy {
+                utf8::Policy::Error => return Poll::Ready(Some(Err(Error::Utf8(e)))),
+                utf8::Policy::Skip => {
+                    *this.skipped_lines += 1;
+                    if let Some(ref tx) = *this.invalid_lines {
+                        let _ = tx.unbounded_send(utf8::InvalidLine {
+                            bytes: line,
+                            offset,
+                            valid_up_to: e.valid_up_to(),
+                        });
+                    }
+                }
+                utf8::Policy::Lossy => {
+                    let repaired = String::from_utf8_lossy(&line).into_owned();
+                    let json = unsafe {
+                        // Safety: `repaired` is a `String`.
+                        string::String::<Bytes>::from_utf8_unchecked(repaired.into())
+                    };
+                    return Poll::Ready(Some(Ok((Some(json), offset))));
+                }
+            }
+        }
+    }
+
+    /// Polls the next JSON message along with its byte offset in the response body,
+    /// skipping the blank lines.
+    #[allow(clippy::type_complexity)]
+    fn poll_next_message(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<(string::String<Bytes>, u64), Error<B::Error>>>> {
+        loop {
+            match ready!(self.as_mut().poll_next_frame(cx)?) {
+                Some((Some(json), offset)) => return Poll::Ready(Some(Ok((json, offset)))),
+                Some((None, _)) => {}
+                None => return Poll::Ready(None),
+            }
+        }
+    }
 }
 
 #[cfg(feature = "hyper")]
@@ -235,10 +442,28 @@ where
     type Output = Result<TwitterStream<B>, Error<E>>;
 
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        let res = match ready!(self.project().response.poll(cx)) {
-            Ok(res) => res,
-            Err(e) => return Poll::Ready(Err(Error::Service(e))),
+        let mut this = self.project();
+
+        let res = loop {
+            let res = match ready!(this.response.as_mut().poll(cx)) {
+                Ok(res) => res,
+                Err(e) => return Poll::Ready(Err(Error::Service(e))),
+            };
+
+            if StatusCode::UNAUTHORIZED == res.status() && this.retry.is_some() {
+                match util::clock_skew(res.headers()) {
+                    Some(skew) if skew.abs() >= MIN_CLOCK_SKEW => {
+                        let retry = this.retry.take().unwrap();
+                        this.response.set(retry(skew));
+                        continue;
+                    }
+                    _ => {}
+                }
+            }
+
+            break res;
         };
+
         let (parts, body) = res.into_parts();
         let Parts {
             status, headers, ..
@@ -258,7 +483,13 @@ where
             Lines::new(gzip::identity(HttpBodyAsStream::new(body)))
         };
 
-        Poll::Ready(Ok(TwitterStream { inner }))
+        Poll::Ready(Ok(TwitterStream {
+            inner,
+            offset: 0,
+            utf8_policy: utf8::Policy::default(),
+            skipped_lines: 0,
+            invalid_lines: None,
+        }))
     }
 }
 
@@ -269,32 +500,10 @@ where
     type Item = Result<string::String<Bytes>, Error<B::Error>>;
 
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        let mut this = self.project();
-
-        loop {
-            let line = match ready!(this.inner.as_mut().poll_next(cx)?) {
-                Some(t) => t,
-                None => return std::task::Poll::Ready(None),
-            };
-
-            if line.iter().all(|&c| is_json_whitespace(c)) {
-                continue;
-            }
-
-            str::from_utf8(&line).map_err(Error::Utf8)?;
-            let line = unsafe {
-                // Safety:
-                // - We have checked above that `line` is valid as UTF-8.
-                // - `Bytes` satisfies the requirements of `string::StableAsRef` trait
-                // (https://github.com/carllerche/string/pull/17)
-                string::String::<Bytes>::from_utf8_unchecked(line)
-            };
-            return Poll::Ready(Some(Ok(line)));
-        }
+        self.poll_next_message(cx)
+            .map(|opt| opt.map(|result| result.map(|(json, _)| json)))
     }
 }
 
-fn is_json_whitespace(c: u8) -> bool {
-    // RFC7159 §2
-    b" \t\n\r".contains(&c)
-}
+/// The minimum clock skew in seconds for `FutureTwitterStream` to retry an unauthorized request.
+const MIN_CLOCK_SKEW: i64 = 30;
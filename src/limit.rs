@@ -0,0 +1,247 @@
+//! Accounting of limit notices.
+//!
+//! When a filter matches more Tweets than the rate allowed to be delivered, Twitter sends
+//! [limit notices][limit] like `{"limit":{"track":1234}}`, whose `track` field is the number of
+//! the undelivered Tweets since the connection was established. The count is reset on each
+//! reconnection.
+//!
+//! [limit]: https://developer.twitter.com/en/docs/tweets/filter-realtime/guides/streaming-message-types
+//!
+//! [`LimitCounter::track`] wraps the stream of each connection in a [`Limits`] stream, which
+//! intercepts the limit notices and adds the newly undelivered Tweets to the running totals of
+//! the `LimitCounter`, so that the totals are kept across reconnections.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! use futures::prelude::*;
+//! use twitter_stream::limit::LimitCounter;
+//! use twitter_stream::Token;
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! let token = Token::new("consumer_key", "consumer_secret", "access_key", "access_secret");
+//! let mut builder = twitter_stream::Builder::new(token);
+//! builder.track("@NASA");
+//! let counter = LimitCounter::new();
+//!
+//! // Wrap the stream of every reconnection with the same `counter`.
+//! let stream = builder.listen().await.unwrap();
+//! counter
+//!     .track(stream)
+//!     .pass_through(false)
+//!     .try_for_each(|json| {
+//!         println!("{}", json);
+//!         future::ok(())
+//!     })
+//!     .await
+//!     .unwrap();
+//!
+//! println!("undelivered Tweets: {}", counter.metrics().undelivered);
+//! # }
+//! ```
+
+use std::borrow::Borrow;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use futures_core::{Stream, TryStream};
+use futures_util::ready;
+use pin_project_lite::pin_project;
+
+use crate::scan;
+
+/// Running totals of the limit notices across connections.
+///
+/// Cloning a `LimitCounter` returns a handle to the same totals.
+#[derive(Clone, Debug, Default)]
+pub struct LimitCounter {
+    shared: Arc<Shared>,
+}
+
+pin_project! {
+    /// A stream intercepting the limit notices of a connection, created by
+    /// [`LimitCounter::track`].
+    #[derive(Debug)]
+    pub struct Limits<S> {
+        #[pin]
+        inner: S,
+        counter: LimitCounter,
+        undelivered: u64,
+        pass_through: bool,
+    }
+}
+
+/// A snapshot of the totals of a [`LimitCounter`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Metrics {
+    /// The number of the undelivered Tweets over all the connections.
+    pub undelivered: u64,
+    /// The number of the limit notices received over all the connections.
+    pub notices: u64,
+    /// The number of the connections tracked by the counter.
+    pub connections: u64,
+}
+
+#[derive(Debug, Default)]
+struct Shared {
+    undelivered: AtomicU64,
+    notices: AtomicU64,
+    connections: AtomicU64,
+}
+
+impl LimitCounter {
+    /// Creates a `LimitCounter` with zero totals.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Wraps `stream`, the stream of JSON messages of a new connection, to count its limit
+    /// notices.
+    pub fn track<S>(&self, stream: S) -> Limits<S> {
+        self.shared.connections.fetch_add(1, Ordering::Relaxed);
+        Limits {
+            inner: stream,
+            counter: self.clone(),
+            undelivered: 0,
+            pass_through: true,
+        }
+    }
+
+    /// Returns a snapshot of the totals.
+    pub fn metrics(&self) -> Metrics {
+        Metrics {
+            undelivered: self.shared.undelivered.load(Ordering::Relaxed),
+            notices: self.shared.notices.load(Ordering::Relaxed),
+            connections: self.shared.connections.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl<S> Limits<S> {
+    /// Set whether to yield the limit notices after counting them.
+    ///
+    /// Defaults to `true`. If set to `false`, the limit notices are swallowed.
+    pub fn pass_through(mut self, pass_through: bool) -> Self {
+        self.pass_through = pass_through;
+        self
+    }
+
+    /// Returns the number of the undelivered Tweets over the connection, i.e. the latest
+    /// count of the limit notices.
+    pub fn undelivered(&self) -> u64 {
+        self.undelivered
+    }
+
+    /// Returns the `LimitCounter` the connection is counted by.
+    pub fn counter(&self) -> &LimitCounter {
+        &self.counter
+    }
+
+    /// Returns a reference to the underlying stream.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Consumes the `Limits`, returning the underlying stream.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S> Stream for Limits<S>
+where
+    S: TryStream,
+    S::Ok: Borrow<str>,
+{
+    type Item = Result<S::Ok, S::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+        loop {
+            let json = match ready!(this.inner.as_mut().try_poll_next(cx)?) {
+                Some(json) => json,
+                None => return Poll::Ready(None),
+            };
+
+            let track = match limit_track(json.borrow()) {
+                Some(track) => track,
+                None => return Poll::Ready(Some(Ok(json))),
+            };
+
+            let shared = &this.counter.shared;
+            shared.notices.fetch_add(1, Ordering::Relaxed);
+            // The counts are cumulative, but the notices may arrive out of order.
+            if track > *this.undelivered {
+                shared
+                    .undelivered
+                    .fetch_add(track - *this.undelivered, Ordering::Relaxed);
+                *this.undelivered = track;
+            }
+
+            if *this.pass_through {
+                return Poll::Ready(Some(Ok(json)));
+            }
+        }
+    }
+}
+
+/// Returns the `track` count if `json` is a limit notice.
+fn limit_track(json: &str) -> Option<u64> {
+    if scan::first_key(json).ok()?.as_deref() != Some("limit") {
+        return None;
+    }
+    let mut values = [None];
+    scan::scan(json, &[&["limit", "track"]], &mut values).ok()?;
+    let [track] = values;
+    Some(track.and_then(|track| track.as_u64()).unwrap_or(0))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use futures::executor::block_on;
+    use futures::stream::{self, TryStreamExt};
+
+    #[test]
+    fn count_across_connections() {
+        let counter = LimitCounter::new();
+
+        let first = stream::iter(
+            vec![
+                r#"{"id":1}"#,
+                r#"{"limit":{"track":10,"timestamp_ms":"1577820376771"}}"#,
+                r#"{"limit":{"track":8}}"#,
+                r#"{"limit":{"track":25}}"#,
+            ]
+            .into_iter()
+            .map(Ok::<_, ()>),
+        );
+        let mut limits = counter.track(first);
+        let messages = block_on((&mut limits).try_collect::<Vec<_>>()).unwrap();
+        assert_eq!(messages.len(), 4);
+        assert_eq!(limits.undelivered(), 25);
+
+        let second = stream::iter(
+            vec![r#"{"limit":{"track":5}}"#, r#"{"id":2}"#]
+                .into_iter()
+                .map(Ok::<_, ()>),
+        );
+        let mut limits = counter.track(second).pass_through(false);
+        let messages = block_on((&mut limits).try_collect::<Vec<_>>()).unwrap();
+        assert_eq!(messages, [r#"{"id":2}"#]);
+        assert_eq!(limits.undelivered(), 5);
+
+        assert_eq!(
+            counter.metrics(),
+            Metrics {
+                undelivered: 30,
+                notices: 4,
+                connections: 2,
+            }
+        );
+    }
+}
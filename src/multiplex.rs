@@ -0,0 +1,482 @@
+//! Multiplexing of several connections into one stream.
+//!
+//! A [`Multiplexer`] holds several connections, each with its own [`Builder`] (and thus its own
+//! token and filter parameters) and HTTP client, and merges them into one stream, tagging each
+//! message with the source connection it came from.
+//!
+//! Each connection is reconnected independently, following the backoff strategy recommended by
+//! [Twitter's documentation][connecting], so that a failing connection does not affect the other
+//! connections. The state of each connection can be inspected with [`Multiplexer::health`].
+//!
+//! [connecting]: https://developer.twitter.com/en/docs/tweets/filter-realtime/guides/connecting
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! use futures::prelude::*;
+//! use twitter_stream::hyper::Multiplexer;
+//! use twitter_stream::{Builder, Token};
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! let conn = hyper_tls::HttpsConnector::new();
+//! let client = hyper_pkg::Client::builder().build::<_, hyper_pkg::Body>(conn);
+//! let mut mux = Multiplexer::new();
+//!
+//! let token = Token::new("consumer_key", "consumer_secret", "access_key", "access_secret");
+//! let mut builder = Builder::new(token);
+//! builder.track("@NASA");
+//! mux.push(builder, client.clone());
+//!
+//! let token = Token::new("consumer_key", "consumer_secret", "access_key", "access_secret");
+//! let mut builder = Builder::new(token);
+//! builder.track("@ESA");
+//! mux.push(builder, client);
+//!
+//! while let Some(result) = mux.next().await {
+//!     match result {
+//!         Ok(message) => println!("{}: {}", message.source, message.json),
+//!         Err(e) => eprintln!("{}", e),
+//!     }
+//! }
+//! # }
+//! ```
+
+use std::borrow::Borrow;
+use std::error;
+use std::fmt::{self, Display, Formatter};
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use futures_core::Stream;
+use futures_util::ready;
+use http::Response;
+use http_body::Body;
+use tokio::time::{delay_for, Delay};
+
+use crate::builder::Builder;
+use crate::error::Error;
+use crate::service::HttpService;
+use crate::token::Token;
+use crate::{FutureTwitterStream, TwitterStream};
+
+/// A stream merging the messages from several connections.
+///
+/// The stream yields an error each time a connection fails, but keeps running until all
+/// the connections have failed permanently, i.e. until all of them have been rejected with
+/// an HTTP client error (other than `420` and `429`), which cannot be resolved by reconnecting.
+pub struct Multiplexer<'a, S, B, C = String, A = String>
+where
+    S: HttpService<B>,
+{
+    sources: Vec<Source<'a, S, B, C, A>>,
+    // The index of the source to poll first, to poll the sources in a round-robin manner.
+    next: usize,
+}
+
+/// A JSON message tagged with the connection it was received from.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Message {
+    /// The index of the source connection, in the order of the calls to
+    /// [`Multiplexer::push`].
+    pub source: usize,
+    /// The JSON message.
+    pub json: string::String<Bytes>,
+}
+
+/// An error occurred on one of the connections of a [`Multiplexer`].
+#[derive(Debug)]
+pub struct SourceError<E = Box<dyn error::Error + Send + Sync>> {
+    /// The index of the connection the error occurred on.
+    pub source: usize,
+    /// The error.
+    pub error: Error<E>,
+}
+
+/// The health of a connection of a [`Multiplexer`].
+#[derive(Clone, Debug)]
+pub struct Health {
+    /// The current state of the connection.
+    pub status: Status,
+    /// The number of times the connection has been established.
+    pub connections: u64,
+    /// The number of failures since the connection was last established.
+    pub failures: u64,
+    /// The number of messages received over all the connections.
+    pub messages: u64,
+    /// The time the last message was received.
+    pub last_message_at: Option<Instant>,
+}
+
+/// The state of a connection of a [`Multiplexer`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Status {
+    /// Waiting for the response from the server.
+    Connecting,
+    /// Receiving messages.
+    Connected,
+    /// Waiting to reconnect after a failure.
+    Waiting {
+        /// The time the connection will be retried.
+        until: Instant,
+    },
+    /// Stopped reconnecting after an unrecoverable error.
+    Failed,
+}
+
+struct Source<'a, S, B, C, A>
+where
+    S: HttpService<B>,
+{
+    builder: Builder<'a, Token<C, A>>,
+    client: S,
+    state: State<S::Future, S::ResponseBody>,
+    backoff: Backoff,
+    health: Health,
+}
+
+enum State<F, B: Body> {
+    Connecting(Pin<Box<FutureTwitterStream<F>>>),
+    Connected(Pin<Box<TwitterStream<B>>>),
+    Waiting(Delay),
+    Failed,
+}
+
+/// Reconnection strategy recommended by Twitter.
+#[derive(Default)]
+struct Backoff {
+    network: Option<Duration>,
+    http: Option<Duration>,
+}
+
+impl<'a, S, B, C, A> Multiplexer<'a, S, B, C, A>
+where
+    S: HttpService<B>,
+{
+    /// Creates a `Multiplexer` without any connection.
+    pub fn new() -> Self {
+        Multiplexer {
+            sources: Vec::new(),
+            next: 0,
+        }
+    }
+
+    /// Returns the number of the connections.
+    pub fn len(&self) -> usize {
+        self.sources.len()
+    }
+
+    /// Returns `true` if the multiplexer has no connection.
+    pub fn is_empty(&self) -> bool {
+        self.sources.is_empty()
+    }
+
+    /// Returns the health of the connection at `source`.
+    ///
+    /// # Panics
+    ///
+    /// This will panic if `source` is out of bounds.
+    pub fn health(&self, source: usize) -> &Health {
+        &self.sources[source].health
+    }
+}
+
+impl<'a, S, B, C, A> Multiplexer<'a, S, B, C, A>
+where
+    C: Borrow<str>,
+    A: Borrow<str>,
+    S: HttpService<B> + Clone,
+    B: From<Vec<u8>>,
+{
+    /// Adds a connection made by `builder` with `client`, returning the index of the connection.
+    ///
+    /// The connection is started immediately, and made again with `builder` and a clone of
+    /// `client` on each reconnection.
+    ///
+    /// # Panics
+    ///
+    /// This will call `<S as Service>::call` without checking for `<S as Service>::poll_ready`
+    /// and may cause a panic if `client` is not ready to send an HTTP request yet.
+    pub fn push(&mut self, builder: Builder<'a, Token<C, A>>, client: S) -> usize {
+        let mut source = Source {
+            builder,
+            client,
+            state: State::Failed,
+            backoff: Backoff::default(),
+            health: Health {
+                status: Status::Connecting,
+                connections: 0,
+                failures: 0,
+                messages: 0,
+                last_message_at: None,
+            },
+        };
+        source.connect();
+        self.sources.push(source);
+        self.sources.len() - 1
+    }
+}
+
+// The connections are never pinned; the futures and streams are boxed.
+impl<'a, S, B, C, A> Unpin for Multiplexer<'a, S, B, C, A> where S: HttpService<B> {}
+
+impl<'a, S, B, C, A> Default for Multiplexer<'a, S, B, C, A>
+where
+    S: HttpService<B>,
+{
+    fn default() -> Self {
+        Multiplexer::new()
+    }
+}
+
+impl<'a, S, B, C, A> Stream for Multiplexer<'a, S, B, C, A>
+where
+    C: Borrow<str>,
+    A: Borrow<str>,
+    S: HttpService<B, Response = Response<<S as HttpService<B>>::ResponseBody>> + Clone,
+    <S::ResponseBody as Body>::Error: Into<S::Error>,
+    B: From<Vec<u8>>,
+{
+    type Item = Result<Message, SourceError<S::Error>>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        let (len, next) = (this.sources.len(), this.next);
+        for i in (0..len).map(|i| (next + i) % len) {
+            if let Poll::Ready(result) = this.sources[i].poll_next(cx) {
+                this.next = i + 1;
+                return Poll::Ready(Some(
+                    result
+                        .map(|json| Message { source: i, json })
+                        .map_err(|error| SourceError { source: i, error }),
+                ));
+            }
+        }
+
+        if this
+            .sources
+            .iter()
+            .all(|source| source.health.status == Status::Failed)
+        {
+            Poll::Ready(None)
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+impl<'a, S, B, C, A> fmt::Debug for Multiplexer<'a, S, B, C, A>
+where
+    S: HttpService<B>,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Multiplexer")
+            .field(
+                "sources",
+                &self
+                    .sources
+                    .iter()
+                    .map(|source| &source.health)
+                    .collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
+
+impl<'a, S, B, C, A> Source<'a, S, B, C, A>
+where
+    C: Borrow<str>,
+    A: Borrow<str>,
+    S: HttpService<B> + Clone,
+    B: From<Vec<u8>>,
+{
+    fn connect(&mut self) {
+        let future = self.builder.listen_with_client(self.client.clone());
+        self.state = State::Connecting(Box::pin(future));
+        self.health.status = Status::Connecting;
+    }
+}
+
+impl<'a, S, B, C, A> Source<'a, S, B, C, A>
+where
+    C: Borrow<str>,
+    A: Borrow<str>,
+    S: HttpService<B, Response = Response<<S as HttpService<B>>::ResponseBody>> + Clone,
+    <S::ResponseBody as Body>::Error: Into<S::Error>,
+    B: From<Vec<u8>>,
+{
+    fn poll_next(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<string::String<Bytes>, Error<S::Error>>> {
+        loop {
+            match self.state {
+                State::Connecting(ref mut future) => match ready!(future.as_mut().poll(cx)) {
+                    Ok(stream) => {
+                        self.state = State::Connected(Box::pin(stream));
+                        self.backoff.reset();
+                        self.health.status = Status::Connected;
+                        self.health.connections += 1;
+                        self.health.failures = 0;
+                    }
+                    Err(e) => return Poll::Ready(Err(self.fail(e))),
+                },
+                State::Connected(ref mut stream) => match ready!(stream.as_mut().poll_next(cx)) {
+                    Some(Ok(json)) => {
+                        self.health.messages += 1;
+                        self.health.last_message_at = Some(Instant::now());
+                        return Poll::Ready(Ok(json));
+                    }
+                    Some(Err(e)) => return Poll::Ready(Err(self.fail(e.map_service(Into::into)))),
+                    None => {
+                        let delay = self.backoff.network();
+                        self.wait(delay);
+                    }
+                },
+                State::Waiting(ref mut delay) => {
+                    ready!(Pin::new(delay).poll(cx));
+                    self.connect();
+                }
+                State::Failed => return Poll::Pending,
+            }
+        }
+    }
+
+    /// Schedules a reconnection after `e` according to the backoff strategy, or stops
+    /// reconnecting if `e` is unrecoverable.
+    fn fail<E>(&mut self, e: Error<E>) -> Error<E> {
+        self.health.failures += 1;
+        let delay = match e {
+            Error::Http(status) if status.as_u16() == 420 => self.backoff.rate_limited(),
+            Error::Http(status) if status.is_client_error() && status.as_u16() != 429 => {
+                self.state = State::Failed;
+                self.health.status = Status::Failed;
+                return e;
+            }
+            Error::Http(_) => self.backoff.http(),
+            _ => self.backoff.network(),
+        };
+        self.wait(delay);
+        e
+    }
+
+    fn wait(&mut self, delay: Duration) {
+        self.state = State::Waiting(delay_for(delay));
+        self.health.status = Status::Waiting {
+            until: Instant::now() + delay,
+        };
+    }
+}
+
+impl<E: error::Error + 'static> error::Error for SourceError<E> {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+impl<E: Display> Display for SourceError<E> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "connection {}: {}", self.source, self.error)
+    }
+}
+
+impl Backoff {
+    fn reset(&mut self) {
+        *self = Backoff::default();
+    }
+
+    /// Back off linearly for TCP/IP level network errors, starting at 250ms up to 16 seconds.
+    fn network(&mut self) -> Duration {
+        let next = self.network.map_or(Duration::from_millis(250), |d| {
+            (d + Duration::from_millis(250)).min(Duration::from_secs(16))
+        });
+        self.network = Some(next);
+        next
+    }
+
+    /// Back off exponentially for HTTP errors, starting at 5 seconds up to 320 seconds.
+    fn http(&mut self) -> Duration {
+        let next = self.http.map_or(Duration::from_secs(5), |d| {
+            (d * 2).min(Duration::from_secs(320))
+        });
+        self.http = Some(next);
+        next
+    }
+
+    /// Back off exponentially for HTTP 420 errors, starting at 1 minute.
+    fn rate_limited(&mut self) -> Duration {
+        let next = self.http.map_or(Duration::from_secs(60), |d| {
+            (d * 2).max(Duration::from_secs(60))
+        });
+        self.http = Some(next);
+        next
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use futures::StreamExt;
+
+    #[tokio::test]
+    async fn reconnect_independently() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let client = {
+            let requests = requests.clone();
+            tower::service_fn(move |req: http::Request<Vec<u8>>| {
+                let res = if req.body().ends_with(b"track=ok") {
+                    let n = requests.fetch_add(1, Ordering::SeqCst);
+                    http::Response::new(hyper_pkg::Body::from(format!("{{\"n\":{}}}\r\n", n)))
+                } else {
+                    http::Response::builder()
+                        .status(http::StatusCode::UNAUTHORIZED)
+                        .body(hyper_pkg::Body::empty())
+                        .unwrap()
+                };
+                futures::future::ok::<_, hyper_pkg::Error>(res)
+            })
+        };
+
+        let mut mux = Multiplexer::new();
+        let mut builder = Builder::new(Token::new("", "", "", ""));
+        builder.track("fail");
+        assert_eq!(mux.push(builder, client.clone()), 0);
+        let mut builder = Builder::new(Token::new("", "", "", ""));
+        builder.track("ok");
+        assert_eq!(mux.push(builder, client), 1);
+
+        let mut messages = Vec::new();
+        let mut errors = Vec::new();
+        while messages.len() < 2 {
+            match mux.next().await.unwrap() {
+                Ok(message) => messages.push((message.source, message.json.to_string())),
+                Err(e) => errors.push(e),
+            }
+        }
+
+        assert_eq!(
+            messages,
+            [(1, "{\"n\":0}".to_owned()), (1, "{\"n\":1}".to_owned())]
+        );
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].source, 0);
+        match errors[0].error {
+            Error::Http(status) => assert_eq!(status, http::StatusCode::UNAUTHORIZED),
+            ref e => panic!("unexpected error: {:?}", e),
+        }
+
+        assert_eq!(mux.health(0).status, Status::Failed);
+        assert_eq!(mux.health(0).failures, 1);
+        assert_eq!(mux.health(1).status, Status::Connected);
+        assert_eq!(mux.health(1).connections, 2);
+        assert_eq!(mux.health(1).messages, 2);
+    }
+}
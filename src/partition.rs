@@ -0,0 +1,105 @@
+//! Merging of partitioned streams.
+//!
+//! The high-volume streams (e.g. the 10% sampled stream) are split into several partitions,
+//! each of which is delivered over its own connection. The `listen_partitions`-like methods
+//! connect to the partitions and merge them into one [`Partitioned`] stream, tagging each message
+//! with the partition it came from.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use bytes::Bytes;
+use futures_core::Stream;
+use futures_util::future;
+use futures_util::stream::{self, SelectAll};
+use http::Response;
+use http_body::Body;
+use pin_project_lite::pin_project;
+
+use crate::error::Error;
+use crate::{FutureTwitterStream, TwitterStream};
+
+/// A stream merging the messages from several partitions of a stream.
+///
+/// The stream ends when all the partitions have ended, and yields an error if any of
+/// the partitions yields an error.
+pub struct Partitioned<B: Body> {
+    inner: SelectAll<Pin<Box<Tag<TwitterStream<B>>>>>,
+}
+
+/// A JSON message tagged with the partition it was received from.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Tagged {
+    /// The partition number of the connection the message was received from.
+    pub partition: u32,
+    /// The JSON message.
+    pub json: string::String<Bytes>,
+}
+
+pin_project! {
+    struct Tag<T> {
+        #[pin]
+        inner: T,
+        partition: u32,
+    }
+}
+
+/// Connects to all the `partitions` and merges them into one stream.
+pub(crate) async fn connect<I, F, B, E>(partitions: I) -> Result<Partitioned<B>, Error<E>>
+where
+    I: IntoIterator<Item = (u32, FutureTwitterStream<F>)>,
+    F: Future<Output = Result<Response<B>, E>>,
+    B: Body,
+{
+    let partitions = partitions
+        .into_iter()
+        .map(|(partition, inner)| Tag { inner, partition });
+    let streams = future::try_join_all(partitions).await?;
+    let inner = stream::select_all(
+        streams
+            .into_iter()
+            .map(|(partition, inner)| Box::pin(Tag { inner, partition })),
+    );
+    Ok(Partitioned { inner })
+}
+
+impl<B: Body> Stream for Partitioned<B> {
+    type Item = Result<Tagged, Error<B::Error>>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.inner).poll_next(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<F, T, E> Future for Tag<F>
+where
+    F: Future<Output = Result<T, E>>,
+{
+    type Output = Result<(u32, T), E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let partition = *this.partition;
+        this.inner.poll(cx).map_ok(|t| (partition, t))
+    }
+}
+
+impl<S, E> Stream for Tag<S>
+where
+    S: Stream<Item = Result<string::String<Bytes>, E>>,
+{
+    type Item = Result<Tagged, E>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        let partition = *this.partition;
+        this.inner
+            .poll_next(cx)
+            .map(|item| item.map(|result| result.map(|json| Tagged { partition, json })))
+    }
+}
@@ -0,0 +1,68 @@
+//! A mode of `TwitterStream` yielding the messages as raw bytes.
+//!
+//! [`TwitterStream::into_raw`](crate::TwitterStream::into_raw) turns a stream into
+//! a [`RawStream`], which yields the framed messages as `Bytes` without validating them as UTF-8.
+//! This saves the validation cost when the messages are not interpreted as strings, e.g. when
+//! the messages are written to storage as-is.
+
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use bytes::Bytes;
+use futures_core::Stream;
+use futures_util::ready;
+use http_body::Body;
+use pin_project_lite::pin_project;
+
+use crate::error::Error;
+use crate::validate;
+use crate::TwitterStream;
+
+pin_project! {
+    /// A stream yielding the messages as raw bytes, created by
+    /// [`TwitterStream::into_raw`](crate::TwitterStream::into_raw).
+    ///
+    /// Like `TwitterStream`, the stream decodes the gzip-encoded responses and discards
+    /// the keep-alive signals, but the yielded messages may not be valid as UTF-8.
+    pub struct RawStream<B: Body> {
+        #[pin]
+        inner: TwitterStream<B>,
+    }
+}
+
+impl<B: Body> RawStream<B> {
+    pub(crate) fn new(inner: TwitterStream<B>) -> Self {
+        RawStream { inner }
+    }
+}
+
+impl<B: Body> Stream for RawStream<B> {
+    type Item = Result<Bytes, Error<B::Error>>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut inner = self.project().inner;
+        loop {
+            match ready!(inner.as_mut().poll_next_line(cx)?) {
+                Some((line, _)) if validate::is_blank(&line) => {}
+                Some((line, _)) => return Poll::Ready(Some(Ok(line))),
+                None => return Poll::Ready(None),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use futures::executor::block_on;
+    use futures::TryStreamExt;
+
+    use crate::util::test::stream_from_body;
+
+    #[test]
+    fn raw() {
+        let stream = stream_from_body(b"{\"a\":1}\r\n\r\n{\"b\":\"\xFF\"}\r\n \r\n");
+
+        let messages: Vec<_> = block_on(stream.into_raw().try_collect::<Vec<_>>()).unwrap();
+        assert_eq!(messages, [&b"{\"a\":1}"[..], &b"{\"b\":\"\xFF\"}"[..]]);
+    }
+}
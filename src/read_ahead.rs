@@ -0,0 +1,427 @@
+//! Reading the stream ahead of the consumer.
+//!
+//! Twitter disconnects the clients that fall behind the stream, but `TwitterStream` only reads
+//! the response body when it is polled, so a consumer that is occasionally slow can get
+//! disconnected. [`TwitterStream::read_ahead`] splits a stream into a [`Driver`], a `Future` to be
+//! spawned on a separate task, which reads the response body into a bounded queue, and
+//! a [`ReadAhead`] stream, which yields the messages from the queue.
+//!
+//! The [`Builder::listen_read_ahead`]-like methods enable the `stall_warnings` parameter, and
+//! `ReadAhead` yields the `FALLING_BEHIND` warnings as [`Event::FallingBehind`] along with
+//! the [`Metrics`] of the queue at the time the warning was read. If the queue is nearly empty
+//! when Twitter warns that the client is falling behind, the bottleneck is the network rather
+//! than the consumer.
+//!
+//! [`TwitterStream::read_ahead`]: crate::TwitterStream::read_ahead
+//! [`Builder::listen_read_ahead`]: crate::Builder::listen_read_ahead
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! use futures::prelude::*;
+//! use twitter_stream::read_ahead::Event;
+//! use twitter_stream::Token;
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! let token = Token::new("consumer_key", "consumer_secret", "access_key", "access_secret");
+//!
+//! let (stream, driver) = twitter_stream::Builder::new(token)
+//!     .listen_read_ahead(4096)
+//!     .await
+//!     .unwrap();
+//! tokio::spawn(driver);
+//!
+//! stream
+//!     .try_for_each(|event| {
+//!         match event {
+//!             Event::Message(json) => println!("{}", json),
+//!             Event::FallingBehind(warning) => eprintln!(
+//!                 "falling behind ({:?}% full on the server side, {} of {} queued locally)",
+//!                 warning.percent_full, warning.metrics.len, warning.metrics.capacity,
+//!             ),
+//!         }
+//!         future::ok(())
+//!     })
+//!     .await
+//!     .unwrap();
+//! # }
+//! ```
+
+use std::collections::VecDeque;
+use std::fmt::{self, Formatter};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+use bytes::Bytes;
+use futures_core::Stream;
+use futures_util::ready;
+use http::Response;
+use http_body::Body;
+use pin_project_lite::pin_project;
+
+use crate::error::Error;
+use crate::scan;
+use crate::{FutureTwitterStream, TwitterStream};
+
+pin_project! {
+    /// A future returned by the [`Builder::listen_read_ahead`](crate::Builder::listen_read_ahead)
+    /// -like methods, resolving to a [`ReadAhead`] stream and its [`Driver`].
+    pub struct FutureReadAhead<F> {
+        #[pin]
+        inner: FutureTwitterStream<F>,
+        capacity: usize,
+    }
+}
+
+/// A stream yielding the messages read ahead by a [`Driver`].
+///
+/// The stream ends when the `Driver` has finished (or has been dropped) and all the queued
+/// messages have been yielded.
+pub struct ReadAhead<B: Body> {
+    shared: Arc<Mutex<Shared<B::Error>>>,
+}
+
+pin_project! {
+    /// A `Future` reading the response body into the queue of a [`ReadAhead`] stream.
+    ///
+    /// The `Driver` stops reading the response while the queue is full, and resolves when
+    /// the response ends, yields an error, or the `ReadAhead` stream is dropped.
+    pub struct Driver<B: Body> {
+        #[pin]
+        inner: TwitterStream<B>,
+        sender: Sender<B::Error>,
+    }
+}
+
+/// An item yielded by [`ReadAhead`].
+#[derive(Clone, Debug)]
+pub enum Event {
+    /// A JSON message.
+    Message(string::String<Bytes>),
+    /// A stall warning with the `FALLING_BEHIND` code.
+    FallingBehind(FallingBehind),
+}
+
+/// A `FALLING_BEHIND` stall warning along with the local queue metrics.
+#[derive(Clone, Debug)]
+pub struct FallingBehind {
+    /// The JSON message of the warning.
+    pub json: string::String<Bytes>,
+    /// The `percent_full` field of the warning, the fill level of the queue on the server side.
+    pub percent_full: Option<u64>,
+    /// The metrics of the local queue at the time the warning was read.
+    pub metrics: Metrics,
+}
+
+/// Metrics of the queue of a [`ReadAhead`] stream.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Metrics {
+    /// The number of the queued messages.
+    pub len: usize,
+    /// The maximum number of the queued messages.
+    pub capacity: usize,
+    /// The largest number of the queued messages so far.
+    pub high_water_mark: usize,
+    /// The number of the messages read into the queue so far.
+    pub received: u64,
+    /// The number of times the driver has stopped reading because the queue was full.
+    pub stalls: u64,
+}
+
+struct Shared<E> {
+    queue: VecDeque<Result<Event, Error<E>>>,
+    metrics: Metrics,
+    // Whether the driver is waiting for the queue to have room.
+    stalled: bool,
+    // Whether the driver has finished.
+    done: bool,
+    // Whether the `ReadAhead` has been dropped.
+    closed: bool,
+    reader: Option<Waker>,
+    driver: Option<Waker>,
+}
+
+// Marks the end of the queue when the driver is dropped.
+struct Sender<E> {
+    shared: Arc<Mutex<Shared<E>>>,
+}
+
+impl<F> FutureReadAhead<F> {
+    pub(crate) fn new(inner: FutureTwitterStream<F>, capacity: usize) -> Self {
+        assert!(capacity > 0, "capacity must be positive");
+        FutureReadAhead { inner, capacity }
+    }
+}
+
+impl<F, B, E> Future for FutureReadAhead<F>
+where
+    F: Future<Output = Result<Response<B>, E>>,
+    B: Body,
+{
+    #[allow(clippy::type_complexity)]
+    type Output = Result<(ReadAhead<B>, Driver<B>), Error<E>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let stream = ready!(this.inner.poll(cx))?;
+        Poll::Ready(Ok(stream.read_ahead(*this.capacity)))
+    }
+}
+
+impl<B: Body> ReadAhead<B> {
+    pub(crate) fn new(inner: TwitterStream<B>, capacity: usize) -> (Self, Driver<B>) {
+        assert!(capacity > 0, "capacity must be positive");
+        let shared = Arc::new(Mutex::new(Shared {
+            queue: VecDeque::with_capacity(capacity),
+            metrics: Metrics {
+                capacity,
+                ..Metrics::default()
+            },
+            stalled: false,
+            done: false,
+            closed: false,
+            reader: None,
+            driver: None,
+        }));
+        let driver = Driver {
+            inner,
+            sender: Sender {
+                shared: shared.clone(),
+            },
+        };
+        (ReadAhead { shared }, driver)
+    }
+
+    /// Returns the current metrics of the queue.
+    pub fn metrics(&self) -> Metrics {
+        self.shared.lock().unwrap().metrics()
+    }
+}
+
+impl<B: Body> Stream for ReadAhead<B> {
+    type Item = Result<Event, Error<B::Error>>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut shared = self.shared.lock().unwrap();
+
+        if let Some(item) = shared.queue.pop_front() {
+            if shared.stalled {
+                shared.stalled = false;
+                if let Some(waker) = shared.driver.take() {
+                    waker.wake();
+                }
+            }
+            return Poll::Ready(Some(item));
+        }
+
+        if shared.done {
+            Poll::Ready(None)
+        } else {
+            shared.reader = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+impl<B: Body> Drop for ReadAhead<B> {
+    fn drop(&mut self) {
+        let mut shared = self.shared.lock().unwrap();
+        shared.closed = true;
+        if let Some(waker) = shared.driver.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl<B: Body> fmt::Debug for ReadAhead<B> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReadAhead")
+            .field("metrics", &self.metrics())
+            .finish()
+    }
+}
+
+impl<B: Body> Future for Driver<B> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let mut this = self.project();
+        let shared = &this.sender.shared;
+
+        loop {
+            {
+                let mut shared = shared.lock().unwrap();
+                if shared.closed || shared.done {
+                    return Poll::Ready(());
+                }
+                if shared.queue.len() >= shared.metrics.capacity {
+                    if !shared.stalled {
+                        shared.stalled = true;
+                        shared.metrics.stalls += 1;
+                    }
+                    shared.driver = Some(cx.waker().clone());
+                    return Poll::Pending;
+                }
+            }
+
+            let item = ready!(this.inner.as_mut().poll_next(cx));
+
+            let mut shared = shared.lock().unwrap();
+            let item = match item {
+                Some(Ok(json)) => {
+                    shared.metrics.received += 1;
+                    Ok(match falling_behind(&json) {
+                        Some(percent_full) => Event::FallingBehind(FallingBehind {
+                            json,
+                            percent_full,
+                            metrics: shared.metrics(),
+                        }),
+                        None => Event::Message(json),
+                    })
+                }
+                Some(Err(e)) => {
+                    shared.done = true;
+                    Err(e)
+                }
+                None => {
+                    shared.done = true;
+                    if let Some(waker) = shared.reader.take() {
+                        waker.wake();
+                    }
+                    return Poll::Ready(());
+                }
+            };
+
+            shared.queue.push_back(item);
+            shared.metrics.high_water_mark = shared.metrics.high_water_mark.max(shared.queue.len());
+            if let Some(waker) = shared.reader.take() {
+                waker.wake();
+            }
+        }
+    }
+}
+
+impl<B: Body> fmt::Debug for Driver<B> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Driver")
+            .field("metrics", &self.sender.shared.lock().unwrap().metrics())
+            .finish()
+    }
+}
+
+impl<E> Shared<E> {
+    fn metrics(&self) -> Metrics {
+        Metrics {
+            len: self.queue.len(),
+            ..self.metrics
+        }
+    }
+}
+
+impl<E> Drop for Sender<E> {
+    fn drop(&mut self) {
+        let mut shared = self.shared.lock().unwrap();
+        shared.done = true;
+        if let Some(waker) = shared.reader.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// Returns `Some(percent_full)` if `json` is a `FALLING_BEHIND` stall warning.
+fn falling_behind(json: &str) -> Option<Option<u64>> {
+    if scan::first_key(json).ok()?.as_deref() != Some("warning") {
+        return None;
+    }
+    let mut values = [None; 2];
+    scan::scan(
+        json,
+        &[&["warning", "code"], &["warning", "percent_full"]],
+        &mut values,
+    )
+    .ok()?;
+    let [code, percent_full] = values;
+    if code?.as_str()? == "FALLING_BEHIND" {
+        Some(percent_full.and_then(|v| v.as_u64()))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use futures::executor::block_on;
+    use futures::TryStreamExt;
+    use tower::Service;
+
+    use crate::util::test::client_from_body;
+
+    #[test]
+    fn read_ahead() {
+        let body = concat!(
+            "{\"n\":0}\r\n",
+            "{\"n\":1}\r\n\r\n",
+            "{\"warning\":{\"code\":\"FALLING_BEHIND\",\"message\":\"\",\"percent_full\":60}}\r\n",
+            "{\"warning\":{\"code\":\"OTHER\"}}\r\n",
+        );
+        let mut inner = client_from_body(body.as_bytes());
+        let client = tower::service_fn(move |req: http::Request<Vec<u8>>| {
+            assert!(req.uri().query().unwrap().contains("stall_warnings=true"));
+            inner.call(req)
+        });
+        let token = crate::Token::new("ck", "cs", "ak", "as");
+        let (mut stream, driver) =
+            block_on(crate::Builder::new(token).listen_read_ahead_with_client(2, client)).unwrap();
+
+        let mut driver = Box::pin(driver);
+        let waker = futures::task::noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        assert!(driver.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(
+            stream.metrics(),
+            Metrics {
+                len: 2,
+                capacity: 2,
+                high_water_mark: 2,
+                received: 2,
+                stalls: 1,
+            }
+        );
+
+        let first = block_on(stream.try_next()).unwrap().unwrap();
+        match first {
+            Event::Message(ref json) => assert_eq!(&**json, "{\"n\":0}"),
+            ref e => panic!("unexpected event: {:?}", e),
+        }
+        assert!(driver.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(stream.metrics().stalls, 2);
+
+        let (events, ()) = block_on(futures::future::join(
+            stream.try_collect::<Vec<_>>(),
+            driver,
+        ));
+        let events = events.unwrap();
+        assert_eq!(events.len(), 3);
+        match events[0] {
+            Event::Message(ref json) => assert_eq!(&**json, "{\"n\":1}"),
+            ref e => panic!("unexpected event: {:?}", e),
+        }
+        match events[1] {
+            Event::FallingBehind(ref warning) => {
+                assert_eq!(warning.percent_full, Some(60));
+                assert_eq!(warning.metrics.len, 1);
+                assert_eq!(warning.metrics.received, 3);
+            }
+            ref e => panic!("unexpected event: {:?}", e),
+        }
+        match events[2] {
+            Event::Message(_) => {}
+            ref e => panic!("unexpected event: {:?}", e),
+        }
+    }
+}
@@ -0,0 +1,364 @@
+//! Resolution of screen names to user IDs for [`Builder::follow`](crate::Builder::follow).
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! use futures::prelude::*;
+//! use twitter_stream::resolve::Resolver;
+//! use twitter_stream::{Builder, Token};
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! let token = Token::new("consumer_key", "consumer_secret", "access_key", "access_secret");
+//!
+//! let mut resolver = Resolver::new(token.clone());
+//! let resolution = resolver.resolve(&["TwitterDev", "TwitterAPI"]).await.unwrap();
+//! for name in &resolution.unresolved {
+//!     eprintln!("unknown or suspended account: @{}", name);
+//! }
+//!
+//! Builder::new(token)
+//!     .follow(resolution.ids)
+//!     .listen()
+//!     .try_flatten_stream()
+//!     .try_for_each(|json| {
+//!         println!("{}", json);
+//!         future::ok(())
+//!     })
+//!     .await
+//!     .unwrap();
+//! # }
+//! ```
+//!
+//! Screen names can change over time. To keep following the same accounts, call
+//! [`Resolver::refresh`] periodically (e.g. when [`Resolver::is_stale`] returns `true`)
+//! and reconnect with the new IDs if [`Resolution::ids`] has changed.
+
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::error;
+use std::fmt::{self, Display, Formatter};
+use std::time::{Duration, Instant};
+
+use http::{Method, StatusCode, Uri};
+use http_body::Body;
+
+use crate::error::Error;
+use crate::service::HttpService;
+use crate::token::Token;
+
+const USERS_LOOKUP: &str = "https://api.twitter.com/1.1/users/lookup.json";
+
+/// The maximum number of screen names per `users/lookup` request.
+const BATCH_SIZE: usize = 100;
+
+/// Resolves screen names to user IDs with [`GET users/lookup`][1], caching the results.
+///
+/// [1]: https://developer.twitter.com/en/docs/accounts-and-users/follow-search-get-users/api-reference/get-users-lookup
+#[derive(Clone, Debug)]
+pub struct Resolver<C = String, T = String> {
+    token: Token<C, T>,
+    // Keyed by lowercased screen names. `None` means that the account is unknown or suspended.
+    cache: HashMap<String, Option<u64>>,
+    resolved_at: Option<Instant>,
+}
+
+/// The result of [`Resolver::resolve`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Resolution {
+    /// IDs of the resolved users, in the order of the given screen names.
+    pub ids: Vec<u64>,
+    /// Screen names that do not belong to any active account
+    /// (i.e. nonexistent or suspended accounts).
+    pub unresolved: Vec<String>,
+}
+
+/// An error occurred while resolving screen names.
+#[derive(Debug)]
+pub enum ResolveError<E = Box<dyn error::Error + Send + Sync>> {
+    /// An error occurred while sending the request or receiving the response.
+    Request(Error<E>),
+    /// Twitter returned a malformed response.
+    Json(serde_json::Error),
+}
+
+/// Parameters of a `users/lookup` request.
+struct Lookup {
+    screen_name: String,
+}
+
+#[derive(serde::Deserialize)]
+struct User {
+    id: u64,
+    screen_name: String,
+}
+
+impl<C: Borrow<str>, T: Borrow<str>> Resolver<C, T> {
+    /// Creates a `Resolver` that authenticates with `token`.
+    pub fn new(token: Token<C, T>) -> Self {
+        Resolver {
+            token,
+            cache: HashMap::new(),
+            resolved_at: None,
+        }
+    }
+
+    /// Resolves `screen_names` to user IDs, querying the API only for the names
+    /// that are not in the cache.
+    ///
+    /// # Panics
+    ///
+    /// This will panic if the underlying HTTPS connector failed to initialize.
+    #[cfg(feature = "hyper")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "hyper")))]
+    pub async fn resolve<N: AsRef<str>>(
+        &mut self,
+        screen_names: &[N],
+    ) -> Result<Resolution, ResolveError<hyper_pkg::Error>> {
+        let client = crate::util::https_client();
+        self.resolve_with_client(screen_names, client).await
+    }
+
+    /// Same as [`resolve`](Resolver::resolve) except that it uses `client` to make HTTP requests
+    /// to the endpoint.
+    pub async fn resolve_with_client<N, S, B>(
+        &mut self,
+        screen_names: &[N],
+        client: S,
+    ) -> Result<Resolution, ResolveError<S::Error>>
+    where
+        N: AsRef<str>,
+        S: HttpService<B, Response = http::Response<<S as HttpService<B>>::ResponseBody>> + Clone,
+        <S::ResponseBody as Body>::Error: Into<S::Error>,
+        B: From<Vec<u8>>,
+    {
+        let names: Vec<String> = screen_names
+            .iter()
+            .map(|name| normalize(name.as_ref()))
+            .collect();
+        let mut missing: Vec<&str> = names
+            .iter()
+            .filter(|name| !self.cache.contains_key(*name))
+            .map(|name| &**name)
+            .collect();
+        missing.sort_unstable();
+        missing.dedup();
+
+        self.lookup(&missing, client).await?;
+
+        Ok(self.resolution(&names))
+    }
+
+    /// Resolves all the screen names in the cache again.
+    ///
+    /// This returns the resolution of all the screen names that have been resolved so far.
+    ///
+    /// # Panics
+    ///
+    /// This will panic if the underlying HTTPS connector failed to initialize.
+    #[cfg(feature = "hyper")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "hyper")))]
+    pub async fn refresh(&mut self) -> Result<Resolution, ResolveError<hyper_pkg::Error>> {
+        let client = crate::util::https_client();
+        self.refresh_with_client(client).await
+    }
+
+    /// Same as [`refresh`](Resolver::refresh) except that it uses `client` to make HTTP requests
+    /// to the endpoint.
+    pub async fn refresh_with_client<S, B>(
+        &mut self,
+        client: S,
+    ) -> Result<Resolution, ResolveError<S::Error>>
+    where
+        S: HttpService<B, Response = http::Response<<S as HttpService<B>>::ResponseBody>> + Clone,
+        <S::ResponseBody as Body>::Error: Into<S::Error>,
+        B: From<Vec<u8>>,
+    {
+        let mut names: Vec<String> = self.cache.keys().cloned().collect();
+        names.sort_unstable();
+        let batch: Vec<&str> = names.iter().map(|name| &**name).collect();
+        self.lookup(&batch, client).await?;
+        Ok(self.resolution(&names))
+    }
+
+    /// Returns `true` if the cache has not been updated for `max_age`.
+    pub fn is_stale(&self, max_age: Duration) -> bool {
+        match self.resolved_at {
+            Some(t) => t.elapsed() >= max_age,
+            None => true,
+        }
+    }
+
+    /// Returns the cached ID for `screen_name`.
+    ///
+    /// This returns `Some(None)` if the screen name is known to be unresolvable,
+    /// and `None` if it has not been resolved yet.
+    pub fn cached(&self, screen_name: &str) -> Option<Option<u64>> {
+        self.cache.get(&normalize(screen_name)).copied()
+    }
+
+    async fn lookup<S, B>(&mut self, names: &[&str], client: S) -> Result<(), ResolveError<S::Error>>
+    where
+        S: HttpService<B, Response = http::Response<<S as HttpService<B>>::ResponseBody>> + Clone,
+        <S::ResponseBody as Body>::Error: Into<S::Error>,
+        B: From<Vec<u8>>,
+    {
+        if names.is_empty() {
+            // Every name came from the cache, which is no fresher than before.
+            return Ok(());
+        }
+
+        for batch in names.chunks(BATCH_SIZE) {
+            let lookup = Lookup {
+                screen_name: batch.join(","),
+            };
+            let res = crate::rest::request_with_client(
+                &Method::POST,
+                &Uri::from_static(USERS_LOOKUP),
+                lookup,
+                &self.token,
+                client.clone(),
+            )
+            .await
+            .map_err(ResolveError::Request)?;
+
+            let users: Vec<User> = match res.status {
+                StatusCode::OK => serde_json::from_slice(&res.body).map_err(ResolveError::Json)?,
+                // `users/lookup` returns `404 Not Found` if none of the users are found.
+                StatusCode::NOT_FOUND => Vec::new(),
+                status => return Err(ResolveError::Request(Error::Http(status))),
+            };
+
+            for &name in batch {
+                self.cache.insert(name.to_owned(), None);
+            }
+            for user in users {
+                self.cache.insert(normalize(&user.screen_name), Some(user.id));
+            }
+        }
+
+        self.resolved_at = Some(Instant::now());
+
+        Ok(())
+    }
+
+    fn resolution(&self, names: &[String]) -> Resolution {
+        let mut ret = Resolution::default();
+        for name in names {
+            match self.cache.get(name) {
+                Some(&Some(id)) => {
+                    if !ret.ids.contains(&id) {
+                        ret.ids.push(id);
+                    }
+                }
+                _ => ret.unresolved.push(name.clone()),
+            }
+        }
+        ret
+    }
+}
+
+// Implemented by hand since `#[derive(oauth::Authorize)]` expands to a non-local `impl`.
+impl oauth::Authorize for Lookup {
+    fn authorize_with<SM>(
+        &self,
+        signer: oauth::signer::Signer<SM>,
+        consumer_key: &str,
+        options: Option<&oauth::Options<'_>>,
+    ) -> oauth::Request
+    where
+        SM: oauth::signature_method::SignatureMethod,
+    {
+        // `screen_name` sorts after the `oauth_*` parameters.
+        let mut signer = signer.oauth_parameters(consumer_key, options);
+        signer.parameter("screen_name", &self.screen_name);
+        signer.finish()
+    }
+}
+
+impl<E: error::Error + 'static> error::Error for ResolveError<E> {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            ResolveError::Request(ref e) => Some(e),
+            ResolveError::Json(ref e) => Some(e),
+        }
+    }
+}
+
+impl<E: Display> Display for ResolveError<E> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match *self {
+            ResolveError::Request(ref e) => Display::fmt(e, f),
+            ResolveError::Json(ref e) => write!(f, "JSON error: {}", e),
+        }
+    }
+}
+
+/// Screen names are case-insensitive and may be written with a leading `@`.
+fn normalize(screen_name: &str) -> String {
+    screen_name.trim_start_matches('@').to_ascii_lowercase()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    #[test]
+    fn resolve() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let client = {
+            let requests = requests.clone();
+            tower::service_fn(move |req: http::Request<Vec<u8>>| {
+                requests.fetch_add(1, Ordering::SeqCst);
+                let body = String::from_utf8(req.into_body()).unwrap();
+                assert_eq!(body, "screen_name=suspended%2Ctwitterapi%2Ctwitterdev");
+                let users = r#"[
+                    {"id":6253282,"screen_name":"TwitterAPI"},
+                    {"id":2244994945,"screen_name":"TwitterDev"}
+                ]"#;
+                let res = http::Response::new(hyper_pkg::Body::from(users));
+                futures::future::ok::<_, hyper_pkg::Error>(res)
+            })
+        };
+
+        let token = Token::new("ck", "cs", "ak", "as");
+        let mut resolver = Resolver::new(token);
+        let names = ["TwitterDev", "@twitterapi", "suspended"];
+
+        let resolution =
+            futures::executor::block_on(resolver.resolve_with_client(&names, client.clone()))
+                .unwrap();
+        assert_eq!(resolution.ids, [2244994945, 6253282]);
+        assert_eq!(resolution.unresolved, ["suspended"]);
+
+        // Served from the cache.
+        let resolution =
+            futures::executor::block_on(resolver.resolve_with_client(&names[..2], client))
+                .unwrap();
+        assert_eq!(resolution.ids, [2244994945, 6253282]);
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn cache_hits_do_not_refresh() {
+        let client = tower::service_fn(|_: http::Request<Vec<u8>>| {
+            let users = r#"[{"id":6253282,"screen_name":"TwitterAPI"}]"#;
+            let res = http::Response::new(hyper_pkg::Body::from(users));
+            futures::future::ok::<_, hyper_pkg::Error>(res)
+        });
+
+        let token = Token::new("ck", "cs", "ak", "as");
+        let mut resolver = Resolver::new(token);
+        assert!(resolver.is_stale(Duration::from_secs(0)));
+
+        futures::executor::block_on(resolver.resolve_with_client(&["TwitterAPI"], client)).unwrap();
+        let resolved_at = resolver.resolved_at.unwrap();
+
+        // Served from the cache.
+        futures::executor::block_on(resolver.resolve_with_client(&["TwitterAPI"], client)).unwrap();
+        assert_eq!(resolver.resolved_at, Some(resolved_at));
+    }
+}
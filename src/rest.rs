@@ -0,0 +1,248 @@
+//! Helpers to make OAuth-signed requests to the REST API alongside the stream.
+//!
+//! The requests are signed in the same way as the requests to the Streaming API.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! use http::Uri;
+//! use twitter_stream::{rest, Token};
+//!
+//! /// Represents a `POST statuses/update` request.
+//! #[derive(oauth::Authorize)]
+//! struct StatusUpdate<'a> {
+//!     status: &'a str,
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! let token = Token::new("consumer_key", "consumer_secret", "access_key", "access_secret");
+//!
+//! let res = rest::post(
+//!     &Uri::from_static("https://api.twitter.com/1.1/statuses/update.json"),
+//!     StatusUpdate { status: "Hello, world!" },
+//!     &token,
+//! )
+//! .await
+//! .unwrap();
+//!
+//! println!("{}", String::from_utf8_lossy(&res.body));
+//! if let Some(rate_limit) = res.rate_limit {
+//!     println!("{} requests remaining", rate_limit.remaining);
+//! }
+//! # }
+//! ```
+
+use std::borrow::Borrow;
+use std::future::Future;
+
+use http::header::HeaderMap;
+use http::{Method, StatusCode, Uri};
+use http_body::Body;
+
+use crate::builder::{sign_request, SigningOptions};
+use crate::error::Error;
+use crate::service::HttpService;
+use crate::token::Token;
+use crate::util::collect_body;
+
+/// A response from the REST API.
+#[derive(Clone, Debug)]
+pub struct Response {
+    /// Status code of the response.
+    pub status: StatusCode,
+    /// Headers of the response.
+    pub headers: HeaderMap,
+    /// Rate limit status of the endpoint, if the response has the `x-rate-limit-*` headers.
+    pub rate_limit: Option<RateLimit>,
+    /// Body of the response.
+    pub body: Vec<u8>,
+}
+
+/// Rate limit status of an endpoint.
+///
+/// See the [Twitter Developer Documentation][1] for more information.
+///
+/// [1]: https://developer.twitter.com/en/docs/basics/rate-limiting
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct RateLimit {
+    /// The rate limit ceiling for the endpoint (`x-rate-limit-limit`).
+    pub limit: u64,
+    /// The number of requests left for the current window (`x-rate-limit-remaining`).
+    pub remaining: u64,
+    /// The UNIX time in seconds when the current window resets (`x-rate-limit-reset`).
+    pub reset: u64,
+}
+
+/// Makes a signed `GET` request to `uri` with `parameters` in the query string.
+///
+/// # Panics
+///
+/// This will panic if the underlying HTTPS connector failed to initialize.
+#[cfg(feature = "hyper")]
+#[cfg_attr(docsrs, doc(cfg(feature = "hyper")))]
+pub fn get<A, C, T>(
+    uri: &Uri,
+    parameters: A,
+    token: &Token<C, T>,
+) -> impl Future<Output = Result<Response, Error<hyper_pkg::Error>>>
+where
+    A: oauth::Authorize,
+    C: Borrow<str>,
+    T: Borrow<str>,
+{
+    let client = crate::util::https_client();
+    request_with_client(&Method::GET, uri, parameters, token, client)
+}
+
+/// Makes a signed `POST` request to `uri` with `parameters` in the
+/// `x-www-form-urlencoded` request body.
+///
+/// # Panics
+///
+/// This will panic if the underlying HTTPS connector failed to initialize.
+#[cfg(feature = "hyper")]
+#[cfg_attr(docsrs, doc(cfg(feature = "hyper")))]
+pub fn post<A, C, T>(
+    uri: &Uri,
+    parameters: A,
+    token: &Token<C, T>,
+) -> impl Future<Output = Result<Response, Error<hyper_pkg::Error>>>
+where
+    A: oauth::Authorize,
+    C: Borrow<str>,
+    T: Borrow<str>,
+{
+    let client = crate::util::https_client();
+    request_with_client(&Method::POST, uri, parameters, token, client)
+}
+
+/// Makes a signed request to `uri` using `client`.
+///
+/// `parameters` are encoded in the `x-www-form-urlencoded` request body if `method` is `POST`,
+/// and in the query string otherwise.
+///
+/// Unlike [`TwitterStream`](crate::TwitterStream), this does not treat non-`200` status codes as
+/// errors, so that the error response body and the rate limit status can be inspected.
+///
+/// # Panics
+///
+/// This will call `<S as Service>::call` without checking for `<S as Service>::poll_ready`
+/// and may cause a panic if `client` is not ready to send an HTTP request yet.
+pub fn request_with_client<A, C, T, S, B>(
+    method: &Method,
+    uri: &Uri,
+    parameters: A,
+    token: &Token<C, T>,
+    mut client: S,
+) -> impl Future<Output = Result<Response, Error<S::Error>>>
+where
+    A: oauth::Authorize,
+    C: Borrow<str>,
+    T: Borrow<str>,
+    S: HttpService<B, Response = http::Response<<S as HttpService<B>>::ResponseBody>>,
+    <S::ResponseBody as Body>::Error: Into<S::Error>,
+    B: From<Vec<u8>>,
+{
+    let req = sign_request(
+        method,
+        uri,
+        token.as_ref(),
+        parameters,
+        &SigningOptions::default(),
+    );
+    let res = client.call(req.map(Into::into));
+
+    async move {
+        let res = res.await.map_err(Error::Service)?;
+        let (parts, body) = res.into_parts();
+        let body = collect_body(body)
+            .await
+            .map_err(|e| e.map_service(Into::into))?;
+        Ok(Response {
+            status: parts.status,
+            rate_limit: RateLimit::from_headers(&parts.headers),
+            headers: parts.headers,
+            body,
+        })
+    }
+}
+
+impl RateLimit {
+    fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let get = |name: &str| headers.get(name)?.to_str().ok()?.parse().ok();
+        Some(RateLimit {
+            limit: get("x-rate-limit-limit")?,
+            remaining: get("x-rate-limit-remaining")?,
+            reset: get("x-rate-limit-reset")?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn signed_request() {
+        let client = tower::service_fn(|req: http::Request<Vec<u8>>| {
+            assert_eq!(req.method(), Method::POST);
+            assert_eq!(
+                req.uri(),
+                "https://api.twitter.com/1.1/statuses/update.json"
+            );
+            assert_eq!(req.body(), b"status=Hello%2C%20world%21");
+            let authorization = req.headers()[http::header::AUTHORIZATION]
+                .to_str()
+                .unwrap();
+            assert!(authorization.starts_with("OAuth "));
+            assert!(authorization.contains(r#"oauth_consumer_key="ck""#));
+            assert!(authorization.contains(r#"oauth_token="ak""#));
+            assert!(authorization.contains("oauth_signature="));
+
+            let res = http::Response::builder()
+                .status(StatusCode::TOO_MANY_REQUESTS)
+                .header("x-rate-limit-limit", "300")
+                .header("x-rate-limit-remaining", "0")
+                .header("x-rate-limit-reset", "1577836800")
+                .body(hyper_pkg::Body::from(r#"{"errors":[]}"#))
+                .unwrap();
+            futures::future::ok::<_, hyper_pkg::Error>(res)
+        });
+
+        let token = Token::new("ck", "cs", "ak", "as");
+        let uri = Uri::from_static("https://api.twitter.com/1.1/statuses/update.json");
+        let parameters: BTreeSet<_> = vec![("status", "Hello, world!")].into_iter().collect();
+        let res = futures::executor::block_on(request_with_client(
+            &Method::POST,
+            &uri,
+            parameters,
+            &token,
+            client,
+        ))
+        .unwrap();
+
+        assert_eq!(res.status, StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(res.body, br#"{"errors":[]}"#);
+        assert_eq!(
+            res.rate_limit,
+            Some(RateLimit {
+                limit: 300,
+                remaining: 0,
+                reset: 1_577_836_800,
+            })
+        );
+    }
+
+    #[test]
+    fn incomplete_rate_limit() {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-rate-limit-limit", "300".parse().unwrap());
+        headers.insert("x-rate-limit-remaining", "299".parse().unwrap());
+        assert_eq!(RateLimit::from_headers(&headers), None);
+        headers.insert("x-rate-limit-reset", "soon".parse().unwrap());
+        assert_eq!(RateLimit::from_headers(&headers), None);
+    }
+}
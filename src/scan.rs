@@ -0,0 +1,567 @@
+//! Extraction of fields from JSON messages without parsing the whole messages.
+//!
+//! Fully deserializing every message can be costly when you only need a few fields of
+//! the messages, e.g. to route the messages. [`scan`] extracts the values of the given paths
+//! from a JSON object in a single pass, skipping the other values without allocating,
+//! and returns them as [`Value`]s borrowing from the message.
+//!
+//! ## Example
+//!
+//! ```rust
+//! use twitter_stream::scan;
+//!
+//! let json = r#"{"id_str":"1","text":"\"Hello\"","lang":"en","user":{"id":12}}"#;
+//!
+//! let mut values = [None; 3];
+//! scan::scan(json, &[&["id_str"], &["lang"], &["user", "id"]], &mut values).unwrap();
+//! let [id_str, lang, user_id] = values;
+//!
+//! assert_eq!(id_str.unwrap().as_str().unwrap(), "1");
+//! assert_eq!(lang.unwrap().as_str().unwrap(), "en");
+//! assert_eq!(user_id.unwrap().as_u64(), Some(12));
+//!
+//! assert_eq!(scan::first_key(r#"{"delete":{}}"#).unwrap().as_deref(), Some("delete"));
+//! ```
+
+use std::borrow::Cow;
+use std::char;
+use std::error;
+use std::fmt::{self, Display, Formatter};
+
+use bytes::Bytes;
+
+/// A JSON value extracted by [`scan`], borrowing from the scanned message.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Value<'j> {
+    raw: &'j str,
+}
+
+/// An error returned when the scanned message is not a valid JSON object.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ScanError {
+    offset: usize,
+}
+
+/// The maximum number of paths that a [`scan`] call can extract.
+pub const MAX_PATHS: usize = 64;
+
+/// Extracts the values of `paths` from the JSON object `json` into `out`.
+///
+/// Each path is a sequence of the keys leading to the value, e.g. `&["user", "id"]` for
+/// the `id` field of the `user` object. `out[i]` is set to the value of `paths[i]`, or `None`
+/// if the message has no such field. If a key appears more than once, the first occurrence is
+/// used.
+///
+/// The scanning stops as soon as all the paths are found, so the rest of the message is not
+/// validated in that case.
+///
+/// # Panics
+///
+/// This will panic if `out` is shorter than `paths` or `paths` has more than [`MAX_PATHS`]
+/// elements.
+pub fn scan<'j>(
+    json: &'j str,
+    paths: &[&[&str]],
+    out: &mut [Option<Value<'j>>],
+) -> Result<(), ScanError> {
+    assert!(paths.len() <= MAX_PATHS, "too many paths");
+    assert!(out.len() >= paths.len(), "`out` is shorter than `paths`");
+
+    for value in out.iter_mut() {
+        *value = None;
+    }
+
+    let all = if paths.len() == MAX_PATHS {
+        !0
+    } else {
+        (1 << paths.len()) - 1
+    };
+    let mut scanner = Scanner {
+        json,
+        pos: 0,
+        paths,
+        out,
+        remaining: all,
+    };
+    scanner.skip_ws();
+    if scanner.peek() != Some(b'{') {
+        return Err(scanner.error());
+    }
+    scanner.object(0, all)?;
+    Ok(())
+}
+
+/// Returns the first key of the JSON object `json`.
+///
+/// The non-Tweet messages of the Streaming API are objects with a single key indicating
+/// the type of the message (e.g. `"delete"` or `"limit"`).
+pub fn first_key(json: &str) -> Result<Option<Cow<'_, str>>, ScanError> {
+    let mut scanner = Scanner {
+        json,
+        pos: 0,
+        paths: &[],
+        out: &mut [],
+        remaining: 0,
+    };
+    scanner.skip_ws();
+    if !scanner.eat(b'{') {
+        return Err(scanner.error());
+    }
+    scanner.skip_ws();
+    match scanner.peek() {
+        Some(b'"') => Ok(Some(scanner.string()?.unescape())),
+        Some(b'}') => Ok(None),
+        _ => Err(scanner.error()),
+    }
+}
+
+impl<'j> Value<'j> {
+    /// Returns the raw JSON text of the value.
+    pub fn raw(&self) -> &'j str {
+        self.raw
+    }
+
+    /// Returns the value as a string if it is a JSON string, unescaping it if necessary.
+    ///
+    /// The returned string borrows from the message unless it contains escape sequences.
+    pub fn as_str(&self) -> Option<Cow<'j, str>> {
+        if self.raw.starts_with('"') {
+            Some(self.unescape())
+        } else {
+            None
+        }
+    }
+
+    /// Returns the value as a `u64` if it is a JSON number representing one.
+    pub fn as_u64(&self) -> Option<u64> {
+        self.raw.parse().ok()
+    }
+
+    /// Returns the value as an `i64` if it is a JSON number representing one.
+    pub fn as_i64(&self) -> Option<i64> {
+        self.raw.parse().ok()
+    }
+
+    /// Returns the value as an `f64` if it is a JSON number.
+    pub fn as_f64(&self) -> Option<f64> {
+        match self.raw.as_bytes().first() {
+            Some(b'-') | Some(b'0'..=b'9') => self.raw.parse().ok(),
+            _ => None,
+        }
+    }
+
+    /// Returns the value as a `bool` if it is a JSON boolean.
+    pub fn as_bool(&self) -> Option<bool> {
+        match self.raw {
+            "true" => Some(true),
+            "false" => Some(false),
+            _ => None,
+        }
+    }
+
+    /// Returns `true` if the value is `null`.
+    pub fn is_null(&self) -> bool {
+        self.raw == "null"
+    }
+
+    /// Returns the raw JSON text of the value as a `Bytes` sharing the buffer of `json`,
+    /// the message the value was extracted from.
+    ///
+    /// # Panics
+    ///
+    /// This will panic if the value was not extracted from `json`.
+    pub fn to_bytes(&self, json: &string::String<Bytes>) -> Bytes {
+        json.get_ref().slice_ref(self.raw.as_bytes())
+    }
+
+    fn unescape(&self) -> Cow<'j, str> {
+        // Strip the quotes.
+        let s = &self.raw[1..self.raw.len() - 1];
+        if !s.contains('\\') {
+            return Cow::Borrowed(s);
+        }
+
+        let mut ret = String::with_capacity(s.len());
+        let mut chars = s.chars();
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                ret.push(c);
+                continue;
+            }
+            // The escape sequences have been validated by the scanner.
+            let c = match chars.next().unwrap() {
+                'b' => '\u{8}',
+                'f' => '\u{c}',
+                'n' => '\n',
+                'r' => '\r',
+                't' => '\t',
+                'u' => {
+                    let hi = hex(&mut chars);
+                    if (0xD800..0xDC00).contains(&hi) {
+                        // A high surrogate must be followed by a low surrogate. Otherwise, it is
+                        // replaced with U+FFFD, leaving the following escape sequence as is.
+                        let mut lookahead = chars.clone();
+                        let lo = if lookahead.as_str().starts_with("\\u") {
+                            lookahead.nth(1);
+                            hex(&mut lookahead)
+                        } else {
+                            0
+                        };
+                        if (0xDC00..0xE000).contains(&lo) {
+                            chars = lookahead;
+                            let c = 0x10000 + ((hi - 0xD800) << 10) + (lo - 0xDC00);
+                            char::from_u32(c).unwrap_or(std::char::REPLACEMENT_CHARACTER)
+                        } else {
+                            std::char::REPLACEMENT_CHARACTER
+                        }
+                    } else {
+                        // A lone low surrogate is not a valid `char` either.
+                        char::from_u32(hi).unwrap_or(std::char::REPLACEMENT_CHARACTER)
+                    }
+                }
+                c => c,
+            };
+            ret.push(c);
+        }
+        Cow::Owned(ret)
+    }
+}
+
+fn hex(chars: &mut std::str::Chars<'_>) -> u32 {
+    let digits = chars.as_str().get(..4).unwrap_or_default();
+    chars.nth(3);
+    u32::from_str_radix(digits, 16).unwrap_or(0xFFFD)
+}
+
+impl<'j> Display for Value<'j> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(self.raw)
+    }
+}
+
+impl ScanError {
+    /// Returns the byte offset in the message where the error was detected.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+}
+
+impl error::Error for ScanError {}
+
+impl Display for ScanError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid JSON object at byte {}", self.offset)
+    }
+}
+
+struct Scanner<'j, 'p, 'o> {
+    json: &'j str,
+    pos: usize,
+    paths: &'p [&'p [&'p str]],
+    out: &'o mut [Option<Value<'j>>],
+    // A bit set of the paths that are not found yet.
+    remaining: u64,
+}
+
+impl<'j, 'p, 'o> Scanner<'j, 'p, 'o> {
+    /// Scans an object at `depth`, extracting the values of the paths in `active`,
+    /// whose first `depth` keys match the keys leading to the object.
+    fn object(&mut self, depth: usize, active: u64) -> Result<(), ScanError> {
+        self.pos += 1; // Skip the `{`.
+        self.skip_ws();
+        if self.eat(b'}') {
+            return Ok(());
+        }
+
+        loop {
+            self.skip_ws();
+            if self.peek() != Some(b'"') {
+                return Err(self.error());
+            }
+            let key = self.string()?;
+            self.skip_ws();
+            if !self.eat(b':') {
+                return Err(self.error());
+            }
+            self.skip_ws();
+
+            let matching = self.matching(depth, active & self.remaining, key);
+            if matching == 0 {
+                self.skip_value()?;
+            } else {
+                let start = self.pos;
+                // Paths ending at this key.
+                let leaves = self.leaves(depth + 1, matching);
+                if leaves != matching && self.peek() == Some(b'{') {
+                    self.object(depth + 1, matching & !leaves)?;
+                } else {
+                    self.skip_value()?;
+                }
+                let value = Value {
+                    raw: &self.json[start..self.pos],
+                };
+                for i in 0..self.paths.len() {
+                    if leaves & (1 << i) != 0 && self.remaining & (1 << i) != 0 {
+                        self.out[i] = Some(value);
+                        self.remaining &= !(1 << i);
+                    }
+                }
+                if self.remaining == 0 {
+                    return Ok(());
+                }
+            }
+
+            self.skip_ws();
+            match self.next_byte() {
+                Some(b',') => {}
+                Some(b'}') => return Ok(()),
+                _ => return Err(self.error_before()),
+            }
+        }
+    }
+
+    fn matching(&self, depth: usize, active: u64, key: Value<'j>) -> u64 {
+        if active == 0 {
+            return 0;
+        }
+        let key = key.unescape();
+        let mut ret = 0;
+        for (i, path) in self.paths.iter().enumerate() {
+            if active & (1 << i) != 0 && path.get(depth) == Some(&&*key) {
+                ret |= 1 << i;
+            }
+        }
+        ret
+    }
+
+    fn leaves(&self, len: usize, set: u64) -> u64 {
+        let mut ret = 0;
+        for (i, path) in self.paths.iter().enumerate() {
+            if set & (1 << i) != 0 && path.len() == len {
+                ret |= 1 << i;
+            }
+        }
+        ret
+    }
+
+    /// Skips a value of any type.
+    fn skip_value(&mut self) -> Result<(), ScanError> {
+        match self.peek() {
+            Some(b'"') => self.string().map(|_| ()),
+            Some(b'{') | Some(b'[') => self.skip_container(),
+            Some(b'-') | Some(b'0'..=b'9') => {
+                let len = self.json.as_bytes()[self.pos..]
+                    .iter()
+                    .position(|&c| !(c.is_ascii_digit() || b"-+.eE".contains(&c)))
+                    .unwrap_or(self.json.len() - self.pos);
+                self.pos += len;
+                Ok(())
+            }
+            _ => {
+                for literal in &["true", "false", "null"] {
+                    if self.json[self.pos..].starts_with(literal) {
+                        self.pos += literal.len();
+                        return Ok(());
+                    }
+                }
+                Err(self.error())
+            }
+        }
+    }
+
+    /// Skips an object or an array without looking into it.
+    fn skip_container(&mut self) -> Result<(), ScanError> {
+        let mut depth = 0_usize;
+        loop {
+            match self.peek() {
+                Some(b'{') | Some(b'[') => {
+                    depth += 1;
+                    self.pos += 1;
+                }
+                Some(b'}') | Some(b']') => {
+                    depth -= 1;
+                    self.pos += 1;
+                    if depth == 0 {
+                        return Ok(());
+                    }
+                }
+                Some(b'"') => {
+                    self.string()?;
+                }
+                Some(_) => self.pos += 1,
+                None => return Err(self.error()),
+            }
+        }
+    }
+
+    /// Scans a string starting at the current position.
+    fn string(&mut self) -> Result<Value<'j>, ScanError> {
+        let start = self.pos;
+        self.pos += 1; // Skip the opening quote.
+        loop {
+            let rest = &self.json.as_bytes()[self.pos..];
+            let i = match memchr::memchr2(b'"', b'\\', rest) {
+                Some(i) => i,
+                None => {
+                    self.pos = self.json.len();
+                    return Err(self.error());
+                }
+            };
+            self.pos += i + 1;
+            if rest[i] == b'"' {
+                return Ok(Value {
+                    raw: &self.json[start..self.pos],
+                });
+            }
+            // Skip the escaped character.
+            match self.next_byte() {
+                Some(b'"') | Some(b'\\') | Some(b'/') | Some(b'b') | Some(b'f') | Some(b'n')
+                | Some(b'r') | Some(b't') => {}
+                Some(b'u') => {
+                    let valid = match self.json.as_bytes().get(self.pos..self.pos + 4) {
+                        Some(hex) => hex.iter().all(u8::is_ascii_hexdigit),
+                        None => false,
+                    };
+                    if !valid {
+                        return Err(self.error());
+                    }
+                    self.pos += 4;
+                }
+                _ => return Err(self.error_before()),
+            }
+        }
+    }
+
+    fn skip_ws(&mut self) {
+        while let Some(b' ') | Some(b'\t') | Some(b'\n') | Some(b'\r') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.json.as_bytes().get(self.pos).copied()
+    }
+
+    fn next_byte(&mut self) -> Option<u8> {
+        let ret = self.peek();
+        self.pos += 1;
+        ret
+    }
+
+    fn eat(&mut self, c: u8) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn error(&self) -> ScanError {
+        ScanError {
+            offset: self.pos.min(self.json.len()),
+        }
+    }
+
+    fn error_before(&self) -> ScanError {
+        ScanError {
+            offset: (self.pos - 1).min(self.json.len()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn nested_and_escaped() {
+        let json = r#" {
+            "text": "a \"quoted\" {\"id_str\":\"0\"} \\",
+            "entities": {"hashtags": [{"id_str": "0", "user": {"id": 0}}]},
+            "id_str": "1é😀",
+            "quoted": {"user": {"id": 2}},
+            "user": {"name": "\\\"}", "id": 12, "lang": "ja"},
+            "lang": "en",
+            "id_str": "3"
+        }"#;
+
+        let mut out = [None; 7];
+        let paths: &[&[&str]] = &[
+            &["id_str"],
+            &["lang"],
+            &["user", "id"],
+            &["user", "missing"],
+            &["entities"],
+            &["quoted", "user", "id"],
+            &["quoted"],
+        ];
+        scan(json, paths, &mut out).unwrap();
+
+        assert_eq!(out[0].unwrap().as_str().unwrap(), "1\u{e9}\u{1F600}");
+        assert_eq!(out[1].unwrap().as_str().unwrap(), "en");
+        assert_eq!(out[2].unwrap().as_u64(), Some(12));
+        assert_eq!(out[3], None);
+        assert_eq!(
+            out[4].unwrap().raw(),
+            r#"{"hashtags": [{"id_str": "0", "user": {"id": 0}}]}"#
+        );
+        assert_eq!(out[5].unwrap().as_u64(), Some(2));
+        assert_eq!(out[6].unwrap().raw(), r#"{"user": {"id": 2}}"#);
+    }
+
+    #[test]
+    fn borrowed() {
+        let json = Bytes::from_static(br#"{"id_str":"1","lang":"en"}"#);
+        // Safety: `json` is an ASCII string.
+        let json = unsafe { string::String::from_utf8_unchecked(json) };
+        let mut out = [None];
+        scan(&json, &[&["lang"]], &mut out).unwrap();
+        let lang = out[0].unwrap();
+        match lang.as_str().unwrap() {
+            Cow::Borrowed("en") => {}
+            s => panic!("unexpected value: {:?}", s),
+        }
+        assert_eq!(lang.to_bytes(&json), "\"en\"");
+    }
+
+    #[test]
+    fn surrogates() {
+        let unescape = |json: &str| {
+            let mut out = [None];
+            scan(json, &[&["a"]], &mut out).unwrap();
+            out[0].unwrap().as_str().unwrap().into_owned()
+        };
+        assert_eq!(unescape(r#"{"a":"\uD83D\uDE00"}"#), "\u{1F600}");
+        assert_eq!(unescape(r#"{"a":"\uD83Dx"}"#), "\u{FFFD}x");
+        assert_eq!(unescape(r#"{"a":"\uD83D"}"#), "\u{FFFD}");
+        assert_eq!(unescape(r#"{"a":"\uDE00\uD83D"}"#), "\u{FFFD}\u{FFFD}");
+        assert_eq!(unescape(r#"{"a":"\uD83D\u0041"}"#), "\u{FFFD}A");
+        assert_eq!(
+            unescape(r#"{"a":"\uD83D\uD83D\uDE00"}"#),
+            "\u{FFFD}\u{1F600}"
+        );
+    }
+
+    #[test]
+    fn invalid() {
+        let mut out = [None];
+        assert_eq!(
+            scan("[]", &[&["a"]], &mut out),
+            Err(ScanError { offset: 0 })
+        );
+        assert_eq!(
+            scan(r#"{"a":{"b":"c}}"#, &[&["a", "x"]], &mut out),
+            Err(ScanError { offset: 14 })
+        );
+        assert_eq!(
+            scan(r#"{"a":1 "b":2}"#, &[&["b"]], &mut out),
+            Err(ScanError { offset: 7 })
+        );
+        assert_eq!(first_key("{}"), Ok(None));
+        assert_eq!(
+            first_key(r#"{"limit":{"track":1}}"#).unwrap().as_deref(),
+            Some("limit")
+        );
+    }
+}
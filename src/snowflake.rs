@@ -0,0 +1,296 @@
+//! Utilities for the Snowflake IDs of Tweets.
+//!
+//! The Tweet IDs are [Snowflake IDs][snowflake], which encode the time the Tweet was created in
+//! milliseconds, along with the datacenter and the worker that generated the ID. Since the IDs
+//! increase with time, they can be used to order the messages, to convert times to the bounds of
+//! the `since_id` and `max_id` parameters of the REST API, and to estimate how long the messages
+//! took to be delivered.
+//!
+//! [snowflake]: https://developer.twitter.com/en/docs/twitter-ids
+//!
+//! ## Example
+//!
+//! ```rust
+//! use std::time::{Duration, UNIX_EPOCH};
+//! use twitter_stream::snowflake::Snowflake;
+//!
+//! let id = Snowflake::new(1212092628029698048);
+//! assert_eq!(id.timestamp(), UNIX_EPOCH + Duration::from_millis(1577820376771));
+//! assert_eq!((id.datacenter(), id.worker(), id.sequence()), (10, 7, 0));
+//!
+//! // The IDs of the Tweets created in 2020 are within this range:
+//! let since = Snowflake::min_at(UNIX_EPOCH + Duration::from_secs(1577836800));
+//! let until = UNIX_EPOCH + Duration::from_secs(1609459200) - Duration::from_millis(1);
+//! let until = Snowflake::max_at(until).unwrap();
+//! assert!(since < until);
+//! ```
+
+use std::fmt::{self, Display, Formatter};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::dedup::tweet_id;
+use crate::envelope::{ConnectionId, Envelope};
+
+/// The time of the Snowflake ID `0`, in milliseconds since the Unix epoch.
+pub const EPOCH_MILLIS: u64 = 1_288_834_974_657;
+
+const TIMESTAMP_SHIFT: u32 = 22;
+const DATACENTER_SHIFT: u32 = 17;
+const WORKER_SHIFT: u32 = 12;
+
+/// A Snowflake ID.
+///
+/// The IDs are ordered by the time they were generated (to the millisecond).
+///
+/// The IDs of the Tweets created before November 2010 are not Snowflake IDs, and the values
+/// decoded from them are meaningless.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Snowflake(u64);
+
+/// A gap in the stream detected by [`GapDetector`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Gap {
+    /// The latest Tweet ID received over the previous connection.
+    pub before: Snowflake,
+    /// The first Tweet ID received over the new connection.
+    pub after: Snowflake,
+    /// The difference between the creation times of `before` and `after`.
+    pub duration: Duration,
+}
+
+/// Detects suspicious gaps between the Tweets received over a connection and those received
+/// over the next connection, which suggest that Tweets were missed while reconnecting.
+///
+/// Feed the [`Envelope`]s of the connections to [`observe`](GapDetector::observe) in
+/// the order they are received.
+#[derive(Clone, Debug)]
+pub struct GapDetector {
+    threshold: Duration,
+    // The connection of the last observed Tweet, and the latest Tweet ID over the connection.
+    last: Option<(ConnectionId, Snowflake)>,
+}
+
+impl Snowflake {
+    /// Creates a `Snowflake` from its integer value.
+    pub const fn new(id: u64) -> Self {
+        Snowflake(id)
+    }
+
+    /// Returns the integer value of the ID.
+    pub const fn as_u64(self) -> u64 {
+        self.0
+    }
+
+    /// Returns the smallest ID that can be generated at or after `time`.
+    ///
+    /// This is suitable for the `since_id` parameter (after subtracting `1`, since
+    /// the parameter is exclusive) to retrieve the Tweets created at or after `time`.
+    ///
+    /// For a `time` before the epoch of the IDs ([`EPOCH_MILLIS`]), this returns the ID `0`.
+    pub fn min_at(time: SystemTime) -> Self {
+        Snowflake(millis_since_epoch(time).unwrap_or(0) << TIMESTAMP_SHIFT)
+    }
+
+    /// Returns the largest ID that can be generated at or before `time`.
+    ///
+    /// This is suitable for the `max_id` parameter to retrieve the Tweets created at or before
+    /// `time`.
+    ///
+    /// Returns `None` if `time` is before the epoch of the IDs ([`EPOCH_MILLIS`]), when no ID
+    /// can be generated.
+    pub fn max_at(time: SystemTime) -> Option<Self> {
+        let millis = millis_since_epoch(time)?;
+        Some(Snowflake(((millis + 1) << TIMESTAMP_SHIFT) - 1))
+    }
+
+    /// Returns the time the ID was generated, in milliseconds since the Unix epoch.
+    pub const fn timestamp_millis(self) -> u64 {
+        (self.0 >> TIMESTAMP_SHIFT) + EPOCH_MILLIS
+    }
+
+    /// Returns the time the ID was generated.
+    pub fn timestamp(self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_millis(self.timestamp_millis())
+    }
+
+    /// Returns the datacenter ID of the ID.
+    pub const fn datacenter(self) -> u8 {
+        ((self.0 >> DATACENTER_SHIFT) & 0x1F) as u8
+    }
+
+    /// Returns the worker ID of the ID.
+    pub const fn worker(self) -> u8 {
+        ((self.0 >> WORKER_SHIFT) & 0x1F) as u8
+    }
+
+    /// Returns the sequence number of the ID in the millisecond.
+    pub const fn sequence(self) -> u16 {
+        (self.0 & 0xFFF) as u16
+    }
+}
+
+impl From<u64> for Snowflake {
+    fn from(id: u64) -> Self {
+        Snowflake(id)
+    }
+}
+
+impl From<Snowflake> for u64 {
+    fn from(id: Snowflake) -> Self {
+        id.0
+    }
+}
+
+impl Display for Snowflake {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Display::fmt(&self.0, f)
+    }
+}
+
+/// Estimates the time taken to deliver the Tweet in `envelope`, from its creation to
+/// its reception.
+///
+/// Returns `None` if the message is not a Tweet, or the Tweet appears to have been received
+/// before it was created (which means the local clock is behind).
+///
+/// The estimate is only as accurate as the local clock, and includes the delay of
+/// the backfilled Tweets and of retweeted Tweets (whose IDs are those of the retweets).
+pub fn latency(envelope: &Envelope) -> Option<Duration> {
+    let id = Snowflake::new(tweet_id(&envelope.json)?);
+    envelope
+        .received_at_system
+        .duration_since(id.timestamp())
+        .ok()
+}
+
+impl GapDetector {
+    /// Creates a `GapDetector` reporting the gaps longer than `threshold`.
+    pub fn new(threshold: Duration) -> Self {
+        GapDetector {
+            threshold,
+            last: None,
+        }
+    }
+
+    /// Observes `envelope`, returning a [`Gap`] if it is the first Tweet received over
+    /// a new connection and it was created more than `threshold` after the latest Tweet received
+    /// over the previous connection.
+    pub fn observe(&mut self, envelope: &Envelope) -> Option<Gap> {
+        let id = Snowflake::new(tweet_id(&envelope.json)?);
+
+        let (gap, latest) = match self.last {
+            Some((connection_id, latest)) if connection_id == envelope.connection_id => {
+                (None, latest.max(id))
+            }
+            Some((_, before)) => {
+                let gap = id
+                    .timestamp()
+                    .duration_since(before.timestamp())
+                    .ok()
+                    .filter(|&duration| duration > self.threshold)
+                    .map(|duration| Gap {
+                        before,
+                        after: id,
+                        duration,
+                    });
+                (gap, id)
+            }
+            None => (None, id),
+        };
+        self.last = Some((envelope.connection_id, latest));
+
+        gap
+    }
+}
+
+/// Returns the milliseconds since the epoch of the IDs, or `None` if `time` is before it.
+fn millis_since_epoch(time: SystemTime) -> Option<u64> {
+    let millis = time.duration_since(UNIX_EPOCH).ok()?.as_millis() as u64;
+    millis.checked_sub(EPOCH_MILLIS)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::time::Instant;
+
+    use bytes::Bytes;
+
+    fn envelope(json: &str, connection_id: ConnectionId) -> Envelope {
+        Envelope {
+            // Safety: `json` is a `str`.
+            json: unsafe {
+                string::String::from_utf8_unchecked(Bytes::copy_from_slice(json.as_bytes()))
+            },
+            received_at: Instant::now(),
+            received_at_system: UNIX_EPOCH + Duration::from_millis(1_577_820_377_000),
+            sequence: 0,
+            offset: 0,
+            connection_id,
+        }
+    }
+
+    #[test]
+    fn bounds() {
+        let id = Snowflake::new(1_212_092_628_029_698_048);
+        let time = id.timestamp();
+        assert!(Snowflake::min_at(time) <= id);
+        assert!(id <= Snowflake::max_at(time).unwrap());
+        assert_eq!(Snowflake::min_at(time).timestamp(), time);
+        assert_eq!(Snowflake::max_at(time).unwrap().timestamp(), time);
+        let before = Snowflake::max_at(time - Duration::from_millis(1)).unwrap();
+        assert_eq!(before.as_u64() + 1, Snowflake::min_at(time).as_u64());
+        assert_eq!(Snowflake::min_at(UNIX_EPOCH), Snowflake::new(0));
+    }
+
+    #[test]
+    fn bounds_before_epoch() {
+        let epoch = UNIX_EPOCH + Duration::from_millis(EPOCH_MILLIS);
+        assert_eq!(
+            Snowflake::max_at(epoch),
+            Some(Snowflake::new((1 << 22) - 1))
+        );
+        assert_eq!(Snowflake::max_at(epoch - Duration::from_millis(1)), None);
+        assert_eq!(Snowflake::max_at(UNIX_EPOCH), None);
+        assert_eq!(
+            Snowflake::min_at(epoch - Duration::from_millis(1)),
+            Snowflake::new(0)
+        );
+    }
+
+    #[test]
+    fn gaps() {
+        let (a, b) = (ConnectionId::next(), ConnectionId::next());
+        let ms = |ms: u64| (ms - EPOCH_MILLIS) << TIMESTAMP_SHIFT;
+        let t0 = 1_577_820_376_771;
+        let json = |id: u64| format!(r#"{{"id":{}}}"#, id);
+
+        let mut detector = GapDetector::new(Duration::from_secs(10));
+        assert_eq!(detector.observe(&envelope(&json(ms(t0 + 1_000)), a)), None);
+        assert_eq!(detector.observe(&envelope(&json(ms(t0)), a)), None);
+        assert_eq!(detector.observe(&envelope(r#"{"limit":{}}"#, b)), None);
+        let gap = detector
+            .observe(&envelope(&json(ms(t0 + 61_000)), b))
+            .unwrap();
+        assert_eq!(gap.before, Snowflake::new(ms(t0 + 1_000)));
+        assert_eq!(gap.duration, Duration::from_secs(60));
+        assert_eq!(detector.observe(&envelope(&json(ms(t0 + 62_000)), b)), None);
+
+        // A reconnection within the threshold.
+        assert_eq!(detector.observe(&envelope(&json(ms(t0 + 65_000)), a)), None);
+    }
+
+    #[test]
+    fn delivery_latency() {
+        let json = r#"{"created_at":"Tue Dec 31 19:26:16 +0000 2019","id":1212092628029698048}"#;
+        assert_eq!(
+            latency(&envelope(json, ConnectionId::next())),
+            Some(Duration::from_millis(229))
+        );
+        assert_eq!(
+            latency(&envelope(r#"{"limit":{}}"#, ConnectionId::next())),
+            None
+        );
+    }
+}
@@ -1,9 +1,34 @@
+//! The [`Token`] type and utilities to load it from the environment or files.
+
 use std::borrow::Borrow;
+use std::env::{self, VarError};
+use std::error;
+use std::fmt::{self, Debug, Display, Formatter};
+use std::io;
+#[cfg(any(feature = "json", feature = "toml"))]
+use std::path::Path;
 
 use oauth::Credentials;
 
+#[cfg(feature = "json")]
+use crate::error::Error;
+#[cfg(feature = "json")]
+use crate::service::HttpService;
+
 /// An OAuth token used to authenticate to Twitter.
-#[derive(Copy, Clone, Debug)]
+///
+/// With the `serde` feature enabled, `Token` implements `Serialize` and `Deserialize` with
+/// the flat representation used by Twitter's developer portal, i.e. a struct with
+/// `consumer_key`, `consumer_secret`, `access_key` and `access_secret` fields
+/// (`api_key`, `api_secret_key`, `access_token` and `access_token_secret` are accepted
+/// as aliases when deserializing).
+///
+/// The `Debug` representation of `Token` never includes the secrets. To keep the secrets out of
+/// `Display`, serde output and memory as well, use [`Token<Secret, Secret>`](Secret), which is
+/// what the loaders ([`Token::from_env`], [`Token::from_file`], etc.) return. Note that a token
+/// of `String`s (e.g. made with [`Token::new`]) is never wiped from memory, even with
+/// the `zeroize` feature enabled.
+#[derive(Copy, Clone)]
 pub struct Token<C = String, T = String> {
     /// Client credentials (aka consumer key and consumer secret, or API key and API secret key)
     pub client: Credentials<C>,
@@ -11,6 +36,108 @@ pub struct Token<C = String, T = String> {
     pub token: Credentials<T>,
 }
 
+/// A string that does not reveal its content unless explicitly asked to.
+///
+/// - `Secret` has no `Display` implementation and its `Debug` implementation prints `<hidden>`.
+/// - With the `serde` feature enabled, `Secret` serializes as `"<hidden>"`. To serialize the
+///   actual value of a `Token<Secret, Secret>`, serialize [`Token::as_ref`] instead.
+/// - With the `zeroize` feature enabled, the content of `Secret` is wiped from memory on drop.
+///   Only `Secret` is wiped, so wrap the strings in `Secret` as soon as you get them.
+///
+/// # Example
+///
+/// ```rust
+/// use twitter_stream::token::Secret;
+/// use twitter_stream::Token;
+///
+/// let token = Token::new("consumer_key", "consumer_secret", "access_key", "access_secret")
+///     .map(Secret::from);
+///
+/// assert_eq!(token.client.secret.expose_secret(), "consumer_secret");
+/// assert_eq!(
+///     format!("{:?}", token),
+///     "Token { \
+///         client: Credentials { identifier: <hidden>, secret: <hidden> }, \
+///         token: Credentials { identifier: <hidden>, secret: <hidden> } \
+///     }",
+/// );
+/// ```
+#[derive(Clone, Default, PartialEq, Eq, Hash)]
+pub struct Secret(String);
+
+/// Information of the authenticated user returned by [`Token::verify_with_client`].
+#[cfg(feature = "json")]
+#[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Verification {
+    /// ID of the user the token belongs to.
+    pub user_id: u64,
+    /// Screen name of the user the token belongs to.
+    pub screen_name: String,
+    /// Difference in seconds between the server's clock and the local clock,
+    /// measured from the `Date` header of the response.
+    ///
+    /// A positive value means that the local clock is behind. The value can be used as
+    /// a timestamp offset for subsequent requests.
+    pub clock_skew: Option<i64>,
+}
+
+/// An error occurred while verifying a [`Token`].
+#[cfg(feature = "json")]
+#[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+#[derive(Debug)]
+pub enum VerifyError<E = Box<dyn error::Error + Send + Sync>> {
+    /// Twitter rejected the token with `401 Unauthorized`.
+    ///
+    /// If `clock_skew` is large (Twitter tolerates a few minutes), the error is likely caused by
+    /// the local clock rather than by the token.
+    Unauthorized {
+        /// Difference in seconds between the server's clock and the local clock.
+        clock_skew: Option<i64>,
+    },
+    /// An error occurred while sending the request or receiving the response.
+    Request(Error<E>),
+    /// Twitter returned a malformed response.
+    Json(serde_json::Error),
+}
+
+/// Names of the environment variables read by [`Token::from_env_with`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct EnvVars<'a> {
+    /// Variable holding the consumer key. Defaults to `TWITTER_CONSUMER_KEY`.
+    pub consumer_key: &'a str,
+    /// Variable holding the consumer secret. Defaults to `TWITTER_CONSUMER_SECRET`.
+    pub consumer_secret: &'a str,
+    /// Variable holding the access token. Defaults to `TWITTER_ACCESS_KEY`.
+    pub access_key: &'a str,
+    /// Variable holding the access token secret. Defaults to `TWITTER_ACCESS_SECRET`.
+    pub access_secret: &'a str,
+}
+
+/// An error occurred while loading a [`Token`] from the environment or a file.
+#[derive(Debug)]
+pub enum LoadError {
+    /// The environment variable of the given name is not set.
+    MissingVar(String),
+    /// The environment variable of the given name is not valid unicode.
+    NotUnicode(String),
+    /// The credential file lacks the given field.
+    MissingField(&'static str),
+    /// The credential file has an extension other than `json` or `toml`
+    /// (or the corresponding feature is not enabled).
+    UnknownFormat,
+    /// An error occurred while reading the credential file.
+    Io(io::Error),
+    /// The credential file is not a valid JSON.
+    #[cfg(feature = "json")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+    Json(serde_json::Error),
+    /// The credential file is not a valid TOML.
+    #[cfg(feature = "toml")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "toml")))]
+    Toml(toml_pkg::de::Error),
+}
+
 impl<C: Borrow<str>, T: Borrow<str>> Token<C, T> {
     /// Creates a new `Token` with the specified client credentials and token credentials.
     pub fn new(
@@ -34,3 +161,433 @@ impl<C: Borrow<str>, T: Borrow<str>> Token<C, T> {
         Token::from_credentials(self.client.as_ref(), self.token.as_ref())
     }
 }
+
+#[cfg(feature = "json")]
+impl<C: Borrow<str>, T: Borrow<str>> Token<C, T> {
+    /// Calls the [`GET account/verify_credentials`][1] endpoint to check that the token is valid,
+    /// resolving to the authenticated user's information.
+    ///
+    /// [1]: https://developer.twitter.com/en/docs/accounts-and-users/manage-account-settings/api-reference/get-account-verify_credentials
+    ///
+    /// # Panics
+    ///
+    /// This will panic if the underlying HTTPS connector failed to initialize.
+    #[cfg(feature = "hyper")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "hyper")))]
+    pub fn verify(
+        &self,
+    ) -> impl std::future::Future<Output = Result<Verification, VerifyError<hyper_pkg::Error>>>
+    {
+        self.verify_with_client(crate::util::https_client())
+    }
+
+    /// Same as [`verify`](Token::verify) except that it uses `client` to make HTTP request
+    /// to the endpoint.
+    ///
+    /// # Panics
+    ///
+    /// This will call `<S as Service>::call` without checking for `<S as Service>::poll_ready`
+    /// and may cause a panic if `client` is not ready to send an HTTP request yet.
+    pub fn verify_with_client<S, B>(
+        &self,
+        client: S,
+    ) -> impl std::future::Future<Output = Result<Verification, VerifyError<S::Error>>>
+    where
+        S: HttpService<B, Response = http::Response<<S as HttpService<B>>::ResponseBody>>,
+        <S::ResponseBody as http_body::Body>::Error: Into<S::Error>,
+        B: From<Vec<u8>>,
+    {
+        const URI: &str = "https://api.twitter.com/1.1/account/verify_credentials.json";
+
+        #[derive(serde::Deserialize)]
+        struct User {
+            id: u64,
+            screen_name: String,
+        }
+
+        let res = crate::rest::request_with_client(
+            &http::Method::GET,
+            &http::Uri::from_static(URI),
+            (),
+            self,
+            client,
+        );
+
+        async move {
+            let res = res.await.map_err(VerifyError::Request)?;
+            let clock_skew = crate::util::clock_skew(&res.headers);
+            match res.status {
+                http::StatusCode::OK => {}
+                http::StatusCode::UNAUTHORIZED => {
+                    return Err(VerifyError::Unauthorized { clock_skew });
+                }
+                status => return Err(VerifyError::Request(Error::Http(status))),
+            }
+
+            let user: User = serde_json::from_slice(&res.body).map_err(VerifyError::Json)?;
+
+            Ok(Verification {
+                user_id: user.id,
+                screen_name: user.screen_name,
+                clock_skew,
+            })
+        }
+    }
+}
+
+impl<T> Token<T, T> {
+    /// Maps the token strings of `self` with `f`.
+    pub fn map<U, F: FnMut(T) -> U>(self, mut f: F) -> Token<U, U> {
+        Token {
+            client: Credentials {
+                identifier: f(self.client.identifier),
+                secret: f(self.client.secret),
+            },
+            token: Credentials {
+                identifier: f(self.token.identifier),
+                secret: f(self.token.secret),
+            },
+        }
+    }
+}
+
+impl Token<Secret, Secret> {
+    /// Loads a `Token` from the environment variables named by [`EnvVars::default`].
+    pub fn from_env() -> Result<Self, LoadError> {
+        Token::from_env_with(&EnvVars::default())
+    }
+
+    /// Loads a `Token` from the environment variables of the specified names.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use twitter_stream::token::EnvVars;
+    /// use twitter_stream::Token;
+    ///
+    /// let token = Token::from_env_with(&EnvVars {
+    ///     consumer_key: "MY_BOT_CONSUMER_KEY",
+    ///     consumer_secret: "MY_BOT_CONSUMER_SECRET",
+    ///     ..EnvVars::default()
+    /// })
+    /// .unwrap();
+    /// ```
+    pub fn from_env_with(vars: &EnvVars<'_>) -> Result<Self, LoadError> {
+        fn var(name: &str) -> Result<String, LoadError> {
+            env::var(name).map_err(|e| match e {
+                VarError::NotPresent => LoadError::MissingVar(name.to_owned()),
+                VarError::NotUnicode(_) => LoadError::NotUnicode(name.to_owned()),
+            })
+        }
+
+        Ok(Token::new(
+            var(vars.consumer_key)?,
+            var(vars.consumer_secret)?,
+            var(vars.access_key)?,
+            var(vars.access_secret)?,
+        )
+        .map(Secret::new))
+    }
+
+    /// Loads a `Token` from a JSON or TOML file, determining the format by the file extension.
+    ///
+    /// See the [`Token`] documentation for the expected fields.
+    #[cfg(any(feature = "json", feature = "toml"))]
+    #[cfg_attr(docsrs, doc(cfg(any(feature = "json", feature = "toml"))))]
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, LoadError> {
+        let path = path.as_ref();
+        match path.extension().and_then(|ext| ext.to_str()) {
+            #[cfg(feature = "json")]
+            Some("json") => Token::from_json_slice(&std::fs::read(path)?),
+            #[cfg(feature = "toml")]
+            Some("toml") => Token::from_toml_str(&std::fs::read_to_string(path)?),
+            _ => Err(LoadError::UnknownFormat),
+        }
+    }
+
+    /// Loads a `Token` from a JSON document.
+    #[cfg(feature = "json")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+    pub fn from_json_slice(json: &[u8]) -> Result<Self, LoadError> {
+        serde_json::from_slice::<serde_imp::Repr<String, String>>(json)
+            .map_err(LoadError::Json)?
+            .into_token()
+            .map(|token| token.map(Secret::new))
+            .map_err(LoadError::MissingField)
+    }
+
+    /// Loads a `Token` from a TOML document.
+    #[cfg(feature = "toml")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "toml")))]
+    pub fn from_toml_str(toml: &str) -> Result<Self, LoadError> {
+        toml_pkg::from_str::<serde_imp::Repr<String, String>>(toml)
+            .map_err(LoadError::Toml)?
+            .into_token()
+            .map(|token| token.map(Secret::new))
+            .map_err(LoadError::MissingField)
+    }
+}
+
+impl Secret {
+    /// Creates a new `Secret`.
+    pub fn new(secret: String) -> Self {
+        Secret(secret)
+    }
+
+    /// Returns the content of the `Secret`.
+    pub fn expose_secret(&self) -> &str {
+        &self.0
+    }
+}
+
+impl<C: Debug, T: Debug> Debug for Token<C, T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        struct Hidden<'a, T>(&'a Credentials<T>);
+
+        impl<'a, T: Debug> Debug for Hidden<'a, T> {
+            fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+                f.debug_struct("Credentials")
+                    .field("identifier", &self.0.identifier)
+                    .field("secret", &format_args!("<hidden>"))
+                    .finish()
+            }
+        }
+
+        f.debug_struct("Token")
+            .field("client", &Hidden(&self.client))
+            .field("token", &Hidden(&self.token))
+            .finish()
+    }
+}
+
+impl Borrow<str> for Secret {
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Debug for Secret {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str("<hidden>")
+    }
+}
+
+impl From<String> for Secret {
+    fn from(secret: String) -> Self {
+        Secret(secret)
+    }
+}
+
+impl<'a> From<&'a str> for Secret {
+    fn from(secret: &'a str) -> Self {
+        Secret(secret.to_owned())
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl Drop for Secret {
+    fn drop(&mut self) {
+        zeroize::Zeroize::zeroize(&mut self.0);
+    }
+}
+
+impl Default for EnvVars<'static> {
+    fn default() -> Self {
+        EnvVars {
+            consumer_key: "TWITTER_CONSUMER_KEY",
+            consumer_secret: "TWITTER_CONSUMER_SECRET",
+            access_key: "TWITTER_ACCESS_KEY",
+            access_secret: "TWITTER_ACCESS_SECRET",
+        }
+    }
+}
+
+impl error::Error for LoadError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        use LoadError::*;
+
+        match *self {
+            MissingVar(_) | NotUnicode(_) | MissingField(_) | UnknownFormat => None,
+            Io(ref e) => Some(e),
+            #[cfg(feature = "json")]
+            Json(ref e) => Some(e),
+            #[cfg(feature = "toml")]
+            Toml(ref e) => Some(e),
+        }
+    }
+}
+
+impl Display for LoadError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        use LoadError::*;
+
+        match *self {
+            MissingVar(ref name) => write!(f, "environment variable `{}` is not set", name),
+            NotUnicode(ref name) => {
+                write!(f, "environment variable `{}` is not valid unicode", name)
+            }
+            MissingField(field) => write!(f, "missing field `{}`", field),
+            UnknownFormat => f.write_str("unknown credential file format"),
+            Io(ref e) => write!(f, "I/O error: {}", e),
+            #[cfg(feature = "json")]
+            Json(ref e) => write!(f, "JSON error: {}", e),
+            #[cfg(feature = "toml")]
+            Toml(ref e) => write!(f, "TOML error: {}", e),
+        }
+    }
+}
+
+#[cfg(feature = "json")]
+impl<E: error::Error + 'static> error::Error for VerifyError<E> {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            VerifyError::Unauthorized { .. } => None,
+            VerifyError::Request(ref e) => Some(e),
+            VerifyError::Json(ref e) => Some(e),
+        }
+    }
+}
+
+#[cfg(feature = "json")]
+impl<E: Display> Display for VerifyError<E> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match *self {
+            VerifyError::Unauthorized {
+                clock_skew: Some(skew),
+            } => write!(
+                f,
+                "invalid or expired token (the local clock is {} seconds off)",
+                skew
+            ),
+            VerifyError::Unauthorized { clock_skew: None } => {
+                f.write_str("invalid or expired token")
+            }
+            VerifyError::Request(ref e) => Display::fmt(e, f),
+            VerifyError::Json(ref e) => write!(f, "JSON error: {}", e),
+        }
+    }
+}
+
+impl From<io::Error> for LoadError {
+    fn from(e: io::Error) -> Self {
+        LoadError::Io(e)
+    }
+}
+
+#[cfg(feature = "serde")]
+mod serde_imp {
+    use std::borrow::Borrow;
+
+    use serde::ser::SerializeStruct;
+    use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::{Secret, Token};
+
+    /// The serialized form of `Token`.
+    ///
+    /// The fields are optional so that callers can tell exactly which field is missing.
+    #[derive(Deserialize)]
+    pub struct Repr<C, T> {
+        #[serde(alias = "api_key")]
+        consumer_key: Option<C>,
+        #[serde(alias = "api_secret_key")]
+        consumer_secret: Option<C>,
+        #[serde(alias = "access_token")]
+        access_key: Option<T>,
+        #[serde(alias = "access_token_secret")]
+        access_secret: Option<T>,
+    }
+
+    impl<C: Borrow<str>, T: Borrow<str>> Repr<C, T> {
+        pub fn into_token(self) -> Result<Token<C, T>, &'static str> {
+            Ok(Token::new(
+                self.consumer_key.ok_or("consumer_key")?,
+                self.consumer_secret.ok_or("consumer_secret")?,
+                self.access_key.ok_or("access_key")?,
+                self.access_secret.ok_or("access_secret")?,
+            ))
+        }
+    }
+
+    impl<C: Serialize, T: Serialize> Serialize for Token<C, T> {
+        fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+            let mut state = s.serialize_struct("Token", 4)?;
+            state.serialize_field("consumer_key", &self.client.identifier)?;
+            state.serialize_field("consumer_secret", &self.client.secret)?;
+            state.serialize_field("access_key", &self.token.identifier)?;
+            state.serialize_field("access_secret", &self.token.secret)?;
+            state.end()
+        }
+    }
+
+    impl Serialize for Secret {
+        fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+            s.serialize_str("<hidden>")
+        }
+    }
+
+    impl<'de> Deserialize<'de> for Secret {
+        fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+            String::deserialize(d).map(Secret)
+        }
+    }
+
+    impl<'de, C, T> Deserialize<'de> for Token<C, T>
+    where
+        C: Borrow<str> + Deserialize<'de>,
+        T: Borrow<str> + Deserialize<'de>,
+    {
+        fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+            Repr::deserialize(d)?
+                .into_token()
+                .map_err(de::Error::missing_field)
+        }
+    }
+}
+
+#[cfg(all(test, feature = "json"))]
+mod test {
+    use super::*;
+
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn verify_with_client() {
+        let client = tower::service_fn(|req: http::Request<Vec<u8>>| {
+            assert!(req.headers()[http::header::AUTHORIZATION]
+                .to_str()
+                .unwrap()
+                .starts_with("OAuth "));
+            let date = SystemTime::now() + Duration::from_secs(600);
+            let res = http::Response::builder()
+                .header(http::header::DATE, httpdate::fmt_http_date(date))
+                .body(hyper_pkg::Body::from(r#"{"id":12,"screen_name":"jack"}"#))
+                .unwrap();
+            futures::future::ok::<_, hyper_pkg::Error>(res)
+        });
+
+        let token = Token::new("ck", "cs", "ak", "as");
+        let verification = futures::executor::block_on(token.verify_with_client(client)).unwrap();
+        assert_eq!(verification.user_id, 12);
+        assert_eq!(verification.screen_name, "jack");
+        let skew = verification.clock_skew.unwrap();
+        assert!((598..=600).contains(&skew), "skew = {}", skew);
+    }
+
+    #[test]
+    fn from_json_slice() {
+        let token = Token::from_json_slice(
+            br#"{"api_key":"ck","consumer_secret":"cs","access_key":"ak","access_secret":"as"}"#,
+        )
+        .unwrap();
+        assert_eq!(token.client.identifier.expose_secret(), "ck");
+        assert_eq!(token.client.secret.expose_secret(), "cs");
+        assert_eq!(token.token.identifier.expose_secret(), "ak");
+        assert_eq!(token.token.secret.expose_secret(), "as");
+
+        match Token::from_json_slice(br#"{"consumer_key":"ck","consumer_secret":"cs"}"#) {
+            Err(LoadError::MissingField("access_key")) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+}
@@ -0,0 +1,126 @@
+//! Handling of the lines that are not valid as UTF-8.
+//!
+//! By default, [`TwitterStream`](crate::TwitterStream) yields an [`Error::Utf8`] when it
+//! encounters a line that is not valid as UTF-8. Set a [`Policy`] with
+//! [`TwitterStream::utf8_policy`](crate::TwitterStream::utf8_policy) to skip or repair such lines
+//! instead, and use [`TwitterStream::invalid_lines`](crate::TwitterStream::invalid_lines) to
+//! receive the skipped lines.
+//!
+//! [`Error::Utf8`]: crate::Error::Utf8
+
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use bytes::Bytes;
+use futures_channel::mpsc;
+use futures_core::Stream;
+
+/// A policy of [`TwitterStream`](crate::TwitterStream) on the lines that are not valid as UTF-8.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Policy {
+    /// Yield an [`Error::Utf8`](crate::Error::Utf8). This is the default.
+    Error,
+    /// Skip the line, counting it and reporting it to the
+    /// [`invalid_lines`](crate::TwitterStream::invalid_lines) channel.
+    Skip,
+    /// Yield the line with the invalid sequences replaced with `U+FFFD REPLACEMENT CHARACTER`.
+    Lossy,
+}
+
+// `#[default]` on enum variants requires Rust 1.62.
+#[allow(clippy::derivable_impls)]
+impl Default for Policy {
+    fn default() -> Self {
+        Policy::Error
+    }
+}
+
+/// A line skipped by [`TwitterStream`](crate::TwitterStream) under [`Policy::Skip`].
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct InvalidLine {
+    /// The raw bytes of the line.
+    pub bytes: Bytes,
+    /// The byte offset of the line in the (decompressed) response body.
+    pub offset: u64,
+    /// The byte offset of the first invalid sequence in the line.
+    pub valid_up_to: usize,
+}
+
+/// A stream of the lines skipped by [`TwitterStream`](crate::TwitterStream), created by
+/// [`TwitterStream::invalid_lines`](crate::TwitterStream::invalid_lines).
+///
+/// The stream ends when the `TwitterStream` is dropped.
+#[derive(Debug)]
+pub struct InvalidLines {
+    rx: mpsc::UnboundedReceiver<InvalidLine>,
+}
+
+impl InvalidLines {
+    pub(crate) fn new() -> (mpsc::UnboundedSender<InvalidLine>, Self) {
+        let (tx, rx) = mpsc::unbounded();
+        (tx, InvalidLines { rx })
+    }
+}
+
+impl Stream for InvalidLines {
+    type Item = InvalidLine;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<InvalidLine>> {
+        Pin::new(&mut self.rx).poll_next(cx)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use futures::executor::block_on;
+    use futures::{StreamExt, TryStreamExt};
+
+    use crate::util::test::stream_from_body;
+
+    fn stream(policy: Policy) -> crate::TwitterStream<hyper_pkg::Body> {
+        let mut stream = stream_from_body(b"{\"a\":1}\r\n{\"b\":\"\xFF\"}\r\n\r\n{}\r\n");
+        stream.utf8_policy(policy);
+        stream
+    }
+
+    #[test]
+    fn error() {
+        let mut stream = stream(Policy::Error);
+        assert_eq!(&*block_on(stream.next()).unwrap().unwrap(), "{\"a\":1}");
+        match block_on(stream.next()).unwrap() {
+            Err(crate::Error::Utf8(e)) => assert_eq!(e.valid_up_to(), 6),
+            _ => panic!("expected a UTF-8 error"),
+        }
+    }
+
+    #[test]
+    fn skip() {
+        let mut stream = stream(Policy::Skip);
+        let invalid_lines = stream.invalid_lines();
+        let messages: Vec<_> = block_on((&mut stream).try_collect::<Vec<_>>()).unwrap();
+        let messages: Vec<&str> = messages.iter().map(|json| &**json).collect();
+        assert_eq!(messages, ["{\"a\":1}", "{}"]);
+        assert_eq!(stream.skipped_lines(), 1);
+
+        drop(stream);
+        let invalid_lines: Vec<_> = block_on(invalid_lines.collect());
+        assert_eq!(
+            invalid_lines,
+            [InvalidLine {
+                bytes: Bytes::from_static(b"{\"b\":\"\xFF\"}"),
+                offset: 9,
+                valid_up_to: 6,
+            }]
+        );
+    }
+
+    #[test]
+    fn lossy() {
+        let stream = stream(Policy::Lossy);
+        let messages: Vec<_> = block_on(stream.try_collect::<Vec<_>>()).unwrap();
+        let messages: Vec<&str> = messages.iter().map(|json| &**json).collect();
+        assert_eq!(messages, ["{\"a\":1}", "{\"b\":\"\u{FFFD}\"}", "{}"]);
+    }
+}
@@ -1,14 +1,19 @@
+use std::borrow::Cow;
 use std::fmt::{self, Display, Formatter};
-use std::mem;
 use std::pin::Pin;
 use std::task::{Context, Poll};
+use std::time::SystemTime;
 
 use bytes::{Buf, Bytes, BytesMut};
+use futures_util::future;
 use futures_util::ready;
 use futures_util::stream::{Fuse, IntoStream, Stream, StreamExt, TryStream, TryStreamExt};
+use http::header::{HeaderMap, DATE};
 use http_body::Body;
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
 use pin_project_lite::pin_project;
 
+use crate::codec::CrlfCodec;
 use crate::error::Error;
 
 /// Creates an enum with `AsRef<str>` impl.
@@ -40,11 +45,49 @@ macro_rules! str_enum {
     }
 }
 
+/// Creates an HTTPS client for the `listen`-like methods.
+#[cfg(feature = "hyper")]
+pub fn https_client() -> crate::hyper::Client {
+    let conn = hyper_tls::HttpsConnector::new();
+    hyper_pkg::Client::builder().build::<_, hyper_pkg::Body>(conn)
+}
+
+/// The characters to be percent-encoded in the user-supplied strings embedded in URI paths,
+/// i.e. all but the unreserved characters of RFC 3986.
+const PATH_SEGMENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// Percent-encodes `segment` to be embedded in a URI path as a single segment.
+pub fn encode_path_segment(segment: Cow<'_, str>) -> Cow<'_, str> {
+    let encoded: Cow<'_, str> = utf8_percent_encode(&segment, PATH_SEGMENT).into();
+    match encoded {
+        Cow::Borrowed(_) => segment,
+        Cow::Owned(encoded) => Cow::Owned(encoded),
+    }
+}
+
+/// Appends the `key=value` pairs of `query` to `uri`.
+pub fn append_query(mut uri: String, query: &[String]) -> String {
+    for (i, pair) in query.iter().enumerate() {
+        uri.push(if i == 0 && !uri.contains('?') {
+            '?'
+        } else {
+            '&'
+        });
+        uri.push_str(pair);
+    }
+    uri
+}
+
 pin_project! {
     pub struct Lines<S> {
         #[pin]
         stream: Fuse<IntoStream<S>>,
         buf: BytesMut,
+        codec: CrlfCodec,
     }
 }
 
@@ -61,6 +104,7 @@ impl<S: TryStream> Lines<S> {
         Lines {
             stream: stream.into_stream().fuse(),
             buf: BytesMut::new(),
+            codec: CrlfCodec::new(),
         }
     }
 }
@@ -71,41 +115,16 @@ impl<S: TryStream<Ok = Bytes, Error = Error<E>>, E> Stream for Lines<S> {
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
         let mut this = self.project();
 
-        if let Some(line) = remove_first_line(&mut this.buf) {
-            return Poll::Ready(Some(Ok(line.freeze())));
-        }
-
-        // Now `self.buf` does not have a CRLF.
-        // Extend the buffer until a CRLF is found.
-
         loop {
-            let mut chunk: BytesMut = loop {
-                if let Some(c) = ready!(this.stream.as_mut().poll_next(cx)) {
-                    let c = c?;
-                    if !c.is_empty() {
-                        // XXX: Copying data to a newly created `BytesMut` because
-                        // `impl From<Bytes> for BytesMut` was removed in `bytes` 0.5.
-                        break c[..].into();
-                    }
-                } else if this.buf.is_empty() {
-                    return Poll::Ready(None);
-                } else {
-                    let ret = mem::replace(this.buf, BytesMut::new()).freeze();
-                    return Poll::Ready(Some(Ok(ret)));
-                }
-            };
-
-            if chunk[0] == b'\n' && this.buf.last() == Some(&b'\r') {
-                // Drop the CRLF
-                chunk.advance(1);
-                let line_len = this.buf.len() - 1;
-                this.buf.truncate(line_len);
-                return Poll::Ready(Some(Ok(mem::replace(this.buf, chunk).freeze())));
-            } else if let Some(line) = remove_first_line(&mut chunk) {
-                this.buf.unsplit(line);
-                return Poll::Ready(Some(Ok(mem::replace(this.buf, chunk).freeze())));
-            } else {
-                this.buf.unsplit(chunk);
+            if let Some(line) = this.codec.decode_line(this.buf) {
+                return Poll::Ready(Some(Ok(line)));
+            }
+
+            // Now `self.buf` does not have a CRLF.
+            // Extend the buffer until a CRLF is found.
+            match ready!(this.stream.as_mut().poll_next(cx)) {
+                Some(chunk) => this.buf.extend_from_slice(&chunk?),
+                None => return Poll::Ready(this.codec.decode_line_eof(this.buf).map(Ok)),
             }
         }
     }
@@ -127,6 +146,29 @@ impl<B: Body> Stream for HttpBodyAsStream<B> {
     }
 }
 
+/// Reads the whole body into a `Vec`.
+pub async fn collect_body<B: Body>(body: B) -> Result<Vec<u8>, Error<B::Error>> {
+    HttpBodyAsStream::new(body)
+        .try_fold(Vec::new(), |mut vec, chunk| {
+            vec.extend_from_slice(&chunk);
+            future::ok(vec)
+        })
+        .await
+}
+
+/// Returns the difference in seconds between the `Date` header in `headers` and the local clock.
+///
+/// A positive value means that the local clock is behind the server's clock.
+pub fn clock_skew(headers: &HeaderMap) -> Option<i64> {
+    let date = headers.get(DATE)?.to_str().ok()?;
+    let date = httpdate::parse_http_date(date).ok()?;
+    let now = SystemTime::now();
+    Some(match date.duration_since(now) {
+        Ok(d) => d.as_secs() as i64,
+        Err(e) => -(e.duration().as_secs() as i64),
+    })
+}
+
 pub fn fmt_join<T: Display>(t: &[T], sep: &str, f: &mut Formatter<'_>) -> fmt::Result {
     let mut iter = t.iter();
     if let Some(t) = iter.next() {
@@ -138,29 +180,34 @@ pub fn fmt_join<T: Display>(t: &[T], sep: &str, f: &mut Formatter<'_>) -> fmt::R
     Ok(())
 }
 
-fn remove_first_line(buf: &mut BytesMut) -> Option<BytesMut> {
-    if buf.len() < 2 {
-        return None;
-    }
-
-    if let Some(i) = memchr::memchr(b'\n', &buf[1..]) {
-        if buf[i] == b'\r' {
-            let mut line = buf.split_to(i + 2);
-            line.truncate(i); // Drop the CRLF
-            return Some(line);
-        }
-    }
-
-    None
-}
-
 #[cfg(test)]
-mod test {
+pub mod test {
     use super::*;
     use bytes::Bytes;
-    use futures::executor::block_on_stream;
+    use futures::executor::{block_on, block_on_stream};
     use futures_util::stream;
 
+    /// Creates a client that responds to every request with `body`.
+    pub fn client_from_body(
+        body: &[u8],
+    ) -> impl tower::Service<
+        http::Request<Vec<u8>>,
+        Response = http::Response<hyper_pkg::Body>,
+        Error = hyper_pkg::Error,
+        Future = future::Ready<Result<http::Response<hyper_pkg::Body>, hyper_pkg::Error>>,
+    > {
+        let body = body.to_vec();
+        tower::service_fn(move |_: http::Request<Vec<u8>>| {
+            future::ok(http::Response::new(hyper_pkg::Body::from(body.clone())))
+        })
+    }
+
+    /// Creates a `TwitterStream` that yields the lines of `body`.
+    pub fn stream_from_body(body: &[u8]) -> crate::TwitterStream<hyper_pkg::Body> {
+        let token = crate::Token::new("ck", "cs", "ak", "as");
+        block_on(crate::Builder::new(token).listen_with_client(client_from_body(body))).unwrap()
+    }
+
     #[test]
     fn lines() {
         let body = [
@@ -0,0 +1,389 @@
+//! Support for the sampled stream of the [Twitter API v2][v2].
+//!
+//! [v2]: https://developer.twitter.com/en/docs/twitter-api/tweets/sampled-stream/introduction
+//!
+//! The v2 endpoints authenticate with an app-only bearer token instead of an OAuth 1.0a
+//! [`Token`](crate::Token). Each message yielded by the stream is a JSON object with
+//! a `data` field containing the Tweet, and an `includes` field containing the objects
+//! requested with [`Builder::expansions`].
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! use futures::prelude::*;
+//! use twitter_stream::v2::{self, Expansion, TweetField};
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! v2::Builder::sample("bearer_token")
+//!     .unwrap()
+//!     .tweet_fields(&[TweetField::CreatedAt, TweetField::Lang][..])
+//!     .expansions(&[Expansion::AuthorId][..])
+//!     .listen()
+//!     .try_flatten_stream()
+//!     .try_for_each(|json| {
+//!         println!("{}", json);
+//!         future::ok(())
+//!     })
+//!     .await
+//!     .unwrap();
+//! # }
+//! ```
+
+use std::borrow::Cow;
+use std::fmt::{self, Formatter};
+use std::future::Future;
+
+use http::header::{HeaderValue, InvalidHeaderValue, AUTHORIZATION};
+use http::{Method, Request, Uri};
+
+use crate::error::Error;
+use crate::partition::{self, Partitioned};
+use crate::service::HttpService;
+#[cfg(feature = "hyper")]
+use crate::util::https_client;
+use crate::util::{append_query, fmt_join};
+use crate::FutureTwitterStream;
+
+/// A builder for connections to the v2 sampled streams.
+///
+/// The `Debug` representation of `Builder` omits the bearer token.
+#[derive(Clone)]
+pub struct Builder<'a> {
+    authorization: HeaderValue,
+    endpoint: Uri,
+    tweet_fields: Cow<'a, [TweetField]>,
+    expansions: Cow<'a, [Expansion]>,
+    backfill_minutes: Option<u32>,
+    partition: Option<u32>,
+}
+
+str_enum! {
+    /// Represents a value of the [`tweet.fields`] parameter in API requests.
+    ///
+    /// [`tweet.fields`]: https://developer.twitter.com/en/docs/twitter-api/data-dictionary/object-model/tweet
+    #[derive(Clone, Copy, Debug, PartialEq, Hash, Eq)]
+    pub enum TweetField {
+        /// `"attachments"`
+        Attachments = "attachments",
+        /// `"author_id"`
+        AuthorId = "author_id",
+        /// `"context_annotations"`
+        ContextAnnotations = "context_annotations",
+        /// `"conversation_id"`
+        ConversationId = "conversation_id",
+        /// `"created_at"`
+        CreatedAt = "created_at",
+        /// `"entities"`
+        Entities = "entities",
+        /// `"geo"`
+        Geo = "geo",
+        /// `"id"`
+        Id = "id",
+        /// `"in_reply_to_user_id"`
+        InReplyToUserId = "in_reply_to_user_id",
+        /// `"lang"`
+        Lang = "lang",
+        /// `"possibly_sensitive"`
+        PossiblySensitive = "possibly_sensitive",
+        /// `"public_metrics"`
+        PublicMetrics = "public_metrics",
+        /// `"referenced_tweets"`
+        ReferencedTweets = "referenced_tweets",
+        /// `"reply_settings"`
+        ReplySettings = "reply_settings",
+        /// `"source"`
+        Source = "source",
+        /// `"text"`
+        Text = "text",
+        /// `"withheld"`
+        Withheld = "withheld",
+    }
+}
+
+str_enum! {
+    /// Represents a value of the [`expansions`] parameter in API requests.
+    ///
+    /// [`expansions`]: https://developer.twitter.com/en/docs/twitter-api/expansions
+    #[derive(Clone, Copy, Debug, PartialEq, Hash, Eq)]
+    pub enum Expansion {
+        /// `"attachments.poll_ids"`
+        AttachmentsPollIds = "attachments.poll_ids",
+        /// `"attachments.media_keys"`
+        AttachmentsMediaKeys = "attachments.media_keys",
+        /// `"author_id"`
+        AuthorId = "author_id",
+        /// `"entities.mentions.username"`
+        EntitiesMentionsUsername = "entities.mentions.username",
+        /// `"geo.place_id"`
+        GeoPlaceId = "geo.place_id",
+        /// `"in_reply_to_user_id"`
+        InReplyToUserId = "in_reply_to_user_id",
+        /// `"referenced_tweets.id"`
+        ReferencedTweetsId = "referenced_tweets.id",
+        /// `"referenced_tweets.id.author_id"`
+        ReferencedTweetsIdAuthorId = "referenced_tweets.id.author_id",
+    }
+}
+
+const SAMPLE: &str = "https://api.twitter.com/2/tweets/sample/stream";
+const SAMPLE10: &str = "https://api.twitter.com/2/tweets/sample10/stream";
+
+impl<'a> Builder<'a> {
+    /// Creates a builder for the 1% sampled stream (`GET /2/tweets/sample/stream`),
+    /// authenticating with `bearer_token`.
+    ///
+    /// Returns an error if `bearer_token` contains characters not allowed in an HTTP header value
+    /// (e.g. control characters).
+    pub fn sample(bearer_token: &str) -> Result<Self, InvalidHeaderValue> {
+        Builder::new(bearer_token, Uri::from_static(SAMPLE))
+    }
+
+    /// Creates a builder for the 10% sampled volume stream (`GET /2/tweets/sample10/stream`),
+    /// authenticating with `bearer_token`.
+    ///
+    /// The stream is split into two partitions, `1` and `2`. Either set one of them with
+    /// [`partition`](Builder::partition) or connect to both of them with
+    /// [`listen_partitions`](Builder::listen_partitions).
+    ///
+    /// Returns an error if `bearer_token` contains characters not allowed in an HTTP header value
+    /// (e.g. control characters).
+    pub fn sample10(bearer_token: &str) -> Result<Self, InvalidHeaderValue> {
+        Builder::new(bearer_token, Uri::from_static(SAMPLE10))
+    }
+
+    fn new(bearer_token: &str, endpoint: Uri) -> Result<Self, InvalidHeaderValue> {
+        let mut authorization = HeaderValue::from_str(&format!("Bearer {}", bearer_token))?;
+        authorization.set_sensitive(true);
+
+        Ok(Builder {
+            authorization,
+            endpoint,
+            tweet_fields: Cow::Borrowed(&[]),
+            expansions: Cow::Borrowed(&[]),
+            backfill_minutes: None,
+            partition: None,
+        })
+    }
+
+    /// Set the URI of the stream to be connected.
+    pub fn endpoint(&mut self, endpoint: Uri) -> &mut Self {
+        self.endpoint = endpoint;
+        self
+    }
+
+    /// Set the fields of the Tweet objects to be returned, in addition to the default
+    /// `id` and `text` fields.
+    pub fn tweet_fields(&mut self, tweet_fields: impl Into<Cow<'a, [TweetField]>>) -> &mut Self {
+        self.tweet_fields = tweet_fields.into();
+        self
+    }
+
+    /// Set the objects referenced by the Tweets to be included in the `includes` field of
+    /// the messages.
+    pub fn expansions(&mut self, expansions: impl Into<Cow<'a, [Expansion]>>) -> &mut Self {
+        self.expansions = expansions.into();
+        self
+    }
+
+    /// Set the number of minutes (up to 5) of Tweets to be delivered on reconnection,
+    /// to recover the Tweets missed during a brief disconnection.
+    ///
+    /// This is only available to the Academic Research access.
+    pub fn backfill_minutes(&mut self, backfill_minutes: impl Into<Option<u32>>) -> &mut Self {
+        self.backfill_minutes = backfill_minutes.into();
+        self
+    }
+
+    /// Set the partition of the stream to connect to.
+    ///
+    /// This is required for the partitioned streams like [`sample10`](Builder::sample10).
+    pub fn partition(&mut self, partition: impl Into<Option<u32>>) -> &mut Self {
+        self.partition = partition.into();
+        self
+    }
+
+    /// Start listening on the stream, returning a `Future` which resolves
+    /// to a `Stream` yielding JSON messages from the API.
+    ///
+    /// # Panics
+    ///
+    /// This will panic if the underlying HTTPS connector failed to initialize.
+    #[cfg(feature = "hyper")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "hyper")))]
+    pub fn listen(&self) -> crate::hyper::FutureTwitterStream {
+        self.listen_with_client(https_client())
+    }
+
+    /// Same as [`listen`](Builder::listen) except that it uses `client` to make HTTP request
+    /// to the endpoint.
+    ///
+    /// # Panics
+    ///
+    /// This will call `<S as Service>::call` without checking for `<S as Service>::poll_ready`
+    /// and may cause a panic if `client` is not ready to send an HTTP request yet.
+    pub fn listen_with_client<S, B>(&self, mut client: S) -> FutureTwitterStream<S::Future>
+    where
+        S: HttpService<B>,
+        B: From<Vec<u8>>,
+    {
+        let req = self.request(self.partition);
+        let response = client.call(req.map(Into::into));
+
+        FutureTwitterStream {
+            response,
+            retry: None,
+        }
+    }
+
+    /// Connects to each of `partitions` of the stream and merges them into one stream,
+    /// tagging each message with the partition it was received from.
+    ///
+    /// This ignores the partition set with [`partition`](Builder::partition).
+    ///
+    /// # Panics
+    ///
+    /// This will panic if the underlying HTTPS connector failed to initialize.
+    #[cfg(feature = "hyper")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "hyper")))]
+    pub fn listen_partitions(
+        &self,
+        partitions: &[u32],
+    ) -> impl Future<Output = Result<crate::hyper::Partitioned, crate::hyper::Error>> {
+        self.listen_partitions_with_client(partitions, https_client())
+    }
+
+    /// Same as [`listen_partitions`](Builder::listen_partitions) except that it uses `client`
+    /// to make HTTP requests to the endpoint.
+    ///
+    /// # Panics
+    ///
+    /// This will call `<S as Service>::call` without checking for `<S as Service>::poll_ready`
+    /// and may cause a panic if `client` is not ready to send an HTTP request yet.
+    pub fn listen_partitions_with_client<S, B>(
+        &self,
+        partitions: &[u32],
+        mut client: S,
+    ) -> impl Future<Output = Result<Partitioned<S::ResponseBody>, Error<S::Error>>>
+    where
+        S: HttpService<B, Response = http::Response<<S as HttpService<B>>::ResponseBody>>,
+        B: From<Vec<u8>>,
+    {
+        let partitions: Vec<_> = partitions
+            .iter()
+            .map(|&partition| {
+                let req = self.request(Some(partition));
+                let response = client.call(req.map(Into::into));
+                let stream = FutureTwitterStream {
+                    response,
+                    retry: None,
+                };
+                (partition, stream)
+            })
+            .collect();
+        partition::connect(partitions)
+    }
+
+    fn request(&self, partition: Option<u32>) -> Request<Vec<u8>> {
+        struct Join<'a, T>(&'a [T]);
+
+        impl<'a, T: AsRef<str>> fmt::Display for Join<'a, T> {
+            fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+                let values: Vec<&str> = self.0.iter().map(AsRef::as_ref).collect();
+                fmt_join(&values, ",", f)
+            }
+        }
+
+        let mut query = Vec::new();
+        if !self.tweet_fields.is_empty() {
+            query.push(format!("tweet.fields={}", Join(&self.tweet_fields)));
+        }
+        if !self.expansions.is_empty() {
+            query.push(format!("expansions={}", Join(&self.expansions)));
+        }
+        if let Some(backfill_minutes) = self.backfill_minutes {
+            query.push(format!("backfill_minutes={}", backfill_minutes));
+        }
+        if let Some(partition) = partition {
+            query.push(format!("partition={}", partition));
+        }
+        let uri = append_query(self.endpoint.to_string(), &query);
+
+        let req = Request::builder()
+            .method(Method::GET)
+            .uri(uri)
+            .header(AUTHORIZATION, self.authorization.clone());
+
+        #[cfg(feature = "gzip")]
+        let req = req.header(
+            http::header::ACCEPT_ENCODING,
+            HeaderValue::from_static("gzip"),
+        );
+
+        req.body(Vec::new()).unwrap()
+    }
+}
+
+impl<'a> fmt::Debug for Builder<'a> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Builder")
+            .field("authorization", &format_args!("<hidden>"))
+            .field("endpoint", &self.endpoint)
+            .field("tweet_fields", &self.tweet_fields)
+            .field("expansions", &self.expansions)
+            .field("backfill_minutes", &self.backfill_minutes)
+            .field("partition", &self.partition)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use futures::executor::block_on;
+    use futures::TryStreamExt;
+
+    #[test]
+    fn merge_partitions() {
+        let client = tower::service_fn(|req: http::Request<Vec<u8>>| {
+            let query = req.uri().query().unwrap();
+            assert!(query.starts_with("tweet.fields=created_at,lang&expansions=author_id&"));
+            let body = if query.ends_with("partition=1") {
+                "{\"data\":1}\r\n\r\n"
+            } else {
+                assert!(query.ends_with("partition=2"));
+                "{\"data\":2}\r\n"
+            };
+            futures::future::ok::<_, hyper_pkg::Error>(http::Response::new(hyper_pkg::Body::from(
+                body,
+            )))
+        });
+
+        let mut builder = Builder::sample10("AAAA").unwrap();
+        builder
+            .tweet_fields(&[TweetField::CreatedAt, TweetField::Lang][..])
+            .expansions(&[Expansion::AuthorId][..]);
+        let stream = block_on(builder.listen_partitions_with_client(&[1, 2], client)).unwrap();
+        let mut messages: Vec<_> = block_on(stream.try_collect::<Vec<_>>())
+            .unwrap()
+            .into_iter()
+            .map(|tagged| (tagged.partition, tagged.json.to_string()))
+            .collect();
+        messages.sort();
+
+        assert_eq!(
+            messages,
+            [
+                (1, "{\"data\":1}".to_owned()),
+                (2, "{\"data\":2}".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_bearer_token() {
+        assert!(Builder::sample("AAAA\n").is_err());
+        assert!(Builder::sample10("AAAA\n").is_err());
+    }
+}
@@ -0,0 +1,247 @@
+//! Validation of the lines of the response body.
+//!
+//! A line is validated in a single pass: the line is scanned in chunks, each of which is checked
+//! for being ASCII and, as long as the line has been blank so far, for being JSON whitespace.
+//! The scan hands the rest of the line over to a UTF-8 validator only at the first chunk
+//! containing a non-ASCII byte, so that every byte is examined once, and a line found to be blank
+//! (i.e. a keep-alive signal) needs no further validation.
+//!
+//! With the `simd` feature, the chunks are scanned with SSE2 on x86_64 and the non-ASCII part
+//! is validated with `simdutf8`. Otherwise, the chunks are scanned eight bytes at a time in
+//! a `u64` and the non-ASCII part is validated with `std::str::from_utf8`.
+
+use std::str::{self, Utf8Error};
+
+/// Checks that `line` is valid as UTF-8, returning `true` if the line is blank
+/// (i.e. a keep-alive signal).
+pub fn validate_line(line: &[u8]) -> Result<bool, Utf8Error> {
+    validate_line_with::<Native>(line)
+}
+
+/// Returns `true` if `line` is blank (i.e. a keep-alive signal), without validating it as UTF-8.
+pub fn is_blank(line: &[u8]) -> bool {
+    is_blank_with::<Native>(line)
+}
+
+#[cfg(all(feature = "simd", target_arch = "x86_64"))]
+type Native = sse2::Sse2;
+#[cfg(not(all(feature = "simd", target_arch = "x86_64")))]
+type Native = swar::Swar;
+
+/// An implementation of the checks on fixed-size chunks of a line.
+trait Chunk {
+    /// The length of the chunks in bytes.
+    const LEN: usize;
+
+    /// Returns `true` if `chunk` only consists of ASCII characters.
+    fn is_ascii(chunk: &[u8]) -> bool;
+
+    /// Returns `true` if `chunk` only consists of JSON whitespaces.
+    fn is_whitespace(chunk: &[u8]) -> bool;
+}
+
+fn validate_line_with<C: Chunk>(line: &[u8]) -> Result<bool, Utf8Error> {
+    let mut chunks = line.chunks_exact(C::LEN);
+    let mut blank = true;
+    let mut offset = 0;
+    for chunk in &mut chunks {
+        // Once a non-whitespace chunk is seen, only the ASCII check is needed.
+        if !(blank && C::is_whitespace(chunk)) {
+            blank = false;
+            if !C::is_ascii(chunk) {
+                return validate_utf8(line, offset).map(|()| false);
+            }
+        }
+        offset += C::LEN;
+    }
+    for (i, &c) in chunks.remainder().iter().enumerate() {
+        if !c.is_ascii() {
+            return validate_utf8(line, offset + i).map(|()| false);
+        }
+        blank &= is_json_whitespace(c);
+    }
+    Ok(blank)
+}
+
+fn is_blank_with<C: Chunk>(line: &[u8]) -> bool {
+    let mut chunks = line.chunks_exact(C::LEN);
+    chunks.all(C::is_whitespace) && chunks.remainder().iter().all(|&c| is_json_whitespace(c))
+}
+
+/// Validates the part of `line` from `start` as UTF-8, where `line[..start]` is ASCII.
+fn validate_utf8(line: &[u8], start: usize) -> Result<(), Utf8Error> {
+    #[cfg(feature = "simd")]
+    let valid = simdutf8::basic::from_utf8(&line[start..]).is_ok();
+    #[cfg(not(feature = "simd"))]
+    let valid = str::from_utf8(&line[start..]).is_ok();
+    if valid {
+        Ok(())
+    } else {
+        // Neither error carries the position relative to the whole line.
+        Err(str::from_utf8(line).unwrap_err())
+    }
+}
+
+fn is_json_whitespace(c: u8) -> bool {
+    // RFC7159 §2
+    b" \t\n\r".contains(&c)
+}
+
+#[cfg(all(feature = "simd", target_arch = "x86_64"))]
+mod sse2 {
+    use std::arch::x86_64::*;
+
+    use super::Chunk;
+
+    /// Scans 16 bytes at a time with SSE2, which is available on all x86_64 CPUs.
+    pub struct Sse2;
+
+    impl Chunk for Sse2 {
+        const LEN: usize = 16;
+
+        fn is_ascii(chunk: &[u8]) -> bool {
+            // Safety: SSE2 is available and `chunk` is 16 bytes long.
+            unsafe { _mm_movemask_epi8(load(chunk)) == 0 }
+        }
+
+        fn is_whitespace(chunk: &[u8]) -> bool {
+            // Safety: SSE2 is available and `chunk` is 16 bytes long.
+            let mask = unsafe {
+                let v = load(chunk);
+                let ws = _mm_or_si128(
+                    _mm_or_si128(
+                        _mm_cmpeq_epi8(v, _mm_set1_epi8(b' ' as i8)),
+                        _mm_cmpeq_epi8(v, _mm_set1_epi8(b'\t' as i8)),
+                    ),
+                    _mm_or_si128(
+                        _mm_cmpeq_epi8(v, _mm_set1_epi8(b'\n' as i8)),
+                        _mm_cmpeq_epi8(v, _mm_set1_epi8(b'\r' as i8)),
+                    ),
+                );
+                _mm_movemask_epi8(ws)
+            };
+            mask == 0xFFFF
+        }
+    }
+
+    unsafe fn load(chunk: &[u8]) -> __m128i {
+        debug_assert_eq!(chunk.len(), 16);
+        _mm_loadu_si128(chunk.as_ptr() as *const __m128i)
+    }
+}
+
+#[cfg(any(test, not(all(feature = "simd", target_arch = "x86_64"))))]
+mod swar {
+    use std::convert::TryInto;
+
+    use super::Chunk;
+
+    /// Scans eight bytes at a time in a `u64` (SIMD within a register).
+    pub struct Swar;
+
+    const LO: u64 = 0x7F7F_7F7F_7F7F_7F7F;
+    const HI: u64 = 0x8080_8080_8080_8080;
+
+    impl Chunk for Swar {
+        const LEN: usize = 8;
+
+        fn is_ascii(chunk: &[u8]) -> bool {
+            load(chunk) & HI == 0
+        }
+
+        fn is_whitespace(chunk: &[u8]) -> bool {
+            const fn splat(c: u8) -> u64 {
+                0x0101_0101_0101_0101 * c as u64
+            }
+            // Sets the high bit of each byte of `x` that is zero.
+            fn zero_bytes(x: u64) -> u64 {
+                !(((x & LO) + LO) | x) & HI
+            }
+
+            let x = load(chunk);
+            let ws = zero_bytes(x ^ splat(b' '))
+                | zero_bytes(x ^ splat(b'\t'))
+                | zero_bytes(x ^ splat(b'\n'))
+                | zero_bytes(x ^ splat(b'\r'));
+            ws == HI
+        }
+    }
+
+    fn load(chunk: &[u8]) -> u64 {
+        u64::from_ne_bytes(chunk.try_into().unwrap())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A xorshift PRNG, to generate the inputs deterministically.
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+    }
+
+    /// The straightforward implementation of `validate_line` to compare the others with.
+    fn validate_line_naive(line: &[u8]) -> Result<bool, Utf8Error> {
+        str::from_utf8(line)?;
+        Ok(line.iter().all(|&c| is_json_whitespace(c)))
+    }
+
+    #[test]
+    fn differential() {
+        let alphabets: &[&[u8]] = &[
+            b" \t\r\n",
+            b" \t\r\n{}\"a",
+            " \n{\"é😀\u{7f}".as_bytes(),
+            b" \t\r\n\x80\xBF\xC0\xC2\xE0\xED\xF0\xF4\xF5\xFF",
+        ];
+
+        let mut rng = Rng(0x2545_F491_4F6C_DD1D);
+        let mut line = Vec::new();
+        for &alphabet in alphabets {
+            for len in 0..80 {
+                for _ in 0..64 {
+                    line.clear();
+                    line.extend((0..len).map(|_| alphabet[rng.next() as usize % alphabet.len()]));
+                    let expected = validate_line_naive(&line);
+                    assert_eq!(validate_line(&line), expected, "line: {:?}", line);
+                    assert_eq!(is_blank(&line), expected == Ok(true), "line: {:?}", line);
+                    assert_eq!(
+                        validate_line_with::<swar::Swar>(&line),
+                        expected,
+                        "line: {:?}",
+                        line
+                    );
+                    assert_eq!(
+                        is_blank_with::<swar::Swar>(&line),
+                        expected == Ok(true),
+                        "line: {:?}",
+                        line
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn blank_lines() {
+        for len in 0..64 {
+            let mut line = vec![b' '; len];
+            assert_eq!(validate_line(&line), Ok(true));
+            for i in 0..len {
+                line[i] = b'{';
+                assert_eq!(validate_line(&line), Ok(false));
+                line[i] = 0xFF;
+                assert!(validate_line(&line).is_err());
+                line[i] = b'\t';
+            }
+        }
+    }
+}
@@ -0,0 +1,344 @@
+//! A receiver for the [Account Activity API][aaa] webhooks.
+//!
+//! [aaa]: https://developer.twitter.com/en/docs/accounts-and-users/subscribe-account-activity/overview
+//!
+//! The Account Activity API delivers the events of the subscribed users by sending `POST`
+//! requests to a webhook URL registered by the app, instead of over a streaming connection.
+//! [`WebhookListener`] runs a local HTTP server for the webhook URL, answering the
+//! [Challenge-Response Checks][crc] and yielding the JSON events as a `Stream`, just like
+//! [`TwitterStream`](crate::TwitterStream).
+//!
+//! [crc]: https://developer.twitter.com/en/docs/accounts-and-users/subscribe-account-activity/guides/securing-webhooks
+//!
+//! If you run your own HTTP server instead, use [`crc_response_token`] and
+//! [`verify_signature`] to implement the security requirements of the webhook.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! use futures::prelude::*;
+//! use twitter_stream::webhook::WebhookListener;
+//! use twitter_stream::Token;
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! let token = Token::new("consumer_key", "consumer_secret", "access_key", "access_secret");
+//!
+//! WebhookListener::bind(&([0, 0, 0, 0], 8080).into(), &token)
+//!     .unwrap()
+//!     .try_for_each(|json| {
+//!         println!("{}", json);
+//!         future::ok(())
+//!     })
+//!     .await
+//!     .unwrap();
+//! # }
+//! ```
+
+use std::borrow::Borrow;
+use std::convert::Infallible;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::str;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use bytes::Bytes;
+use futures_channel::mpsc;
+use futures_core::Stream;
+use hmac::{Hmac, Mac, NewMac};
+use http_body::Body as _;
+use hyper_pkg::server::conn::AddrStream;
+use hyper_pkg::service::{make_service_fn, service_fn};
+use hyper_pkg::{Body, Method, Request, Response, Server, StatusCode};
+use sha2::Sha256;
+
+use crate::auth::parse_query;
+use crate::error::Error;
+use crate::token::Token;
+
+/// The name of the header containing the signature of a webhook request.
+pub const SIGNATURE_HEADER: &str = "x-twitter-webhooks-signature";
+
+/// The maximum length in bytes of a request body accepted by [`WebhookListener`].
+///
+/// The bodies are read into memory before their signatures can be verified, so the length is
+/// limited to keep unauthenticated requests from exhausting the memory.
+pub const MAX_BODY_LEN: usize = 1024 * 1024;
+
+/// A local HTTP server receiving the events of the Account Activity API.
+///
+/// The listener answers the `GET` requests with a `crc_token` parameter with the
+/// Challenge-Response Check response, and yields the bodies of the `POST` requests with a valid
+/// `x-twitter-webhooks-signature` header. The requests with an invalid signature are rejected
+/// with `403 Forbidden`, and those with a body larger than [`MAX_BODY_LEN`] with
+/// `413 Payload Too Large`.
+///
+/// Like [`TwitterStream`](crate::TwitterStream), the listener yields each event as a JSON string.
+/// The server is driven by polling the `Stream`.
+pub struct WebhookListener {
+    local_addr: SocketAddr,
+    server: Option<Pin<Box<dyn Future<Output = hyper_pkg::Result<()>> + Send>>>,
+    events: mpsc::UnboundedReceiver<Bytes>,
+}
+
+impl WebhookListener {
+    /// Binds the listener to `addr`, authenticating the requests with the consumer secret of
+    /// `token`.
+    ///
+    /// Set the port of `addr` to `0` to let the OS choose an unused port.
+    pub fn bind<C, A>(addr: &SocketAddr, token: &Token<C, A>) -> hyper_pkg::Result<Self>
+    where
+        C: Borrow<str>,
+    {
+        let secret: Arc<str> = token.client.secret.borrow().into();
+        let (tx, events) = mpsc::unbounded();
+
+        let make_service = make_service_fn(move |_: &AddrStream| {
+            let secret = secret.clone();
+            let tx = tx.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                    let secret = secret.clone();
+                    let tx = tx.clone();
+                    async move { Ok::<_, Infallible>(handle(req, &secret, &tx).await) }
+                }))
+            }
+        });
+
+        let server = Server::try_bind(addr)?.serve(make_service);
+        let local_addr = server.local_addr();
+
+        Ok(WebhookListener {
+            local_addr,
+            server: Some(Box::pin(server)),
+            events,
+        })
+    }
+
+    /// Returns the local address that the listener is bound to.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+}
+
+impl Stream for WebhookListener {
+    type Item = Result<string::String<Bytes>, Error<hyper_pkg::Error>>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if let Some(ref mut server) = self.server {
+            if let Poll::Ready(result) = server.as_mut().poll(cx) {
+                self.server = None;
+                if let Err(e) = result {
+                    return Poll::Ready(Some(Err(Error::Service(e))));
+                }
+            }
+        }
+
+        let event = match Pin::new(&mut self.events).poll_next(cx) {
+            Poll::Ready(Some(event)) => event,
+            // The server has shut down and all the events have been yielded.
+            Poll::Ready(None) => return Poll::Ready(None),
+            Poll::Pending if self.server.is_none() => return Poll::Ready(None),
+            Poll::Pending => return Poll::Pending,
+        };
+
+        str::from_utf8(&event).map_err(Error::Utf8)?;
+        let event = unsafe {
+            // Safety:
+            // - We have checked above that `event` is valid as UTF-8.
+            // - `Bytes` satisfies the requirements of `string::StableAsRef` trait
+            // (https://github.com/carllerche/string/pull/17)
+            string::String::<Bytes>::from_utf8_unchecked(event)
+        };
+        Poll::Ready(Some(Ok(event)))
+    }
+}
+
+impl std::fmt::Debug for WebhookListener {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WebhookListener")
+            .field("local_addr", &self.local_addr)
+            .finish()
+    }
+}
+
+/// Computes the `response_token` for the Challenge-Response Check with `crc_token`.
+///
+/// The returned value is in the form of `sha256=<base64-encoded HMAC-SHA256>`.
+pub fn crc_response_token(crc_token: &str, consumer_secret: &str) -> String {
+    format!(
+        "sha256={}",
+        base64::encode(sign(crc_token.as_bytes(), consumer_secret))
+    )
+}
+
+/// Checks that `signature`, the value of the `x-twitter-webhooks-signature` header,
+/// is a valid signature of `body` signed with `consumer_secret`.
+///
+/// The comparison is done in constant time.
+pub fn verify_signature(body: &[u8], signature: &[u8], consumer_secret: &str) -> bool {
+    const PREFIX: &[u8] = b"sha256=";
+    if !signature.starts_with(PREFIX) {
+        return false;
+    }
+    let signature = match base64::decode(&signature[PREFIX.len()..]) {
+        Ok(signature) => signature,
+        Err(_) => return false,
+    };
+
+    let mut mac = Hmac::<Sha256>::new_varkey(consumer_secret.as_bytes()).unwrap();
+    mac.update(body);
+    mac.verify(&signature).is_ok()
+}
+
+fn sign(message: &[u8], consumer_secret: &str) -> impl AsRef<[u8]> {
+    let mut mac = Hmac::<Sha256>::new_varkey(consumer_secret.as_bytes()).unwrap();
+    mac.update(message);
+    mac.finalize().into_bytes()
+}
+
+async fn handle(
+    req: Request<Body>,
+    secret: &str,
+    events: &mpsc::UnboundedSender<Bytes>,
+) -> Response<Body> {
+    let status = match *req.method() {
+        Method::GET => {
+            let query = parse_query(req.uri().query().unwrap_or_default());
+            if let Some((_, crc_token)) = query.iter().find(|(k, _)| k == "crc_token") {
+                let body = format!(
+                    r#"{{"response_token":"{}"}}"#,
+                    crc_response_token(crc_token, secret)
+                );
+                return Response::builder()
+                    .header(http::header::CONTENT_TYPE, "application/json")
+                    .body(Body::from(body))
+                    .unwrap();
+            }
+            StatusCode::BAD_REQUEST
+        }
+        Method::POST => {
+            let (parts, body) = req.into_parts();
+            let too_large = parts
+                .headers
+                .get(http::header::CONTENT_LENGTH)
+                .and_then(|len| len.to_str().ok()?.parse::<u64>().ok())
+                .map(|len| len > MAX_BODY_LEN as u64)
+                .unwrap_or(false);
+            if too_large {
+                StatusCode::PAYLOAD_TOO_LARGE
+            } else {
+                let signature = parts.headers.get(SIGNATURE_HEADER);
+                match (collect_body_limited(body, MAX_BODY_LEN).await, signature) {
+                    (Ok(Some(body)), Some(signature))
+                        if verify_signature(&body, signature.as_bytes(), secret) =>
+                    {
+                        let _ = events.unbounded_send(body.into());
+                        StatusCode::OK
+                    }
+                    (Ok(Some(_)), _) => StatusCode::FORBIDDEN,
+                    (Ok(None), _) => StatusCode::PAYLOAD_TOO_LARGE,
+                    (Err(_), _) => StatusCode::BAD_REQUEST,
+                }
+            }
+        }
+        _ => StatusCode::METHOD_NOT_ALLOWED,
+    };
+
+    Response::builder()
+        .status(status)
+        .body(Body::empty())
+        .unwrap()
+}
+
+/// Reads `body` into memory, returning `None` as soon as it exceeds `limit` bytes.
+async fn collect_body_limited(mut body: Body, limit: usize) -> hyper_pkg::Result<Option<Vec<u8>>> {
+    let mut buf = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk?;
+        if buf.len() + chunk.len() > limit {
+            return Ok(None);
+        }
+        buf.extend_from_slice(&chunk);
+    }
+    Ok(Some(buf))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use futures::StreamExt;
+
+    use crate::util::collect_body;
+
+    #[test]
+    fn crc() {
+        // `printf crc_token | openssl dgst -sha256 -hmac consumer_secret -binary | base64`
+        assert_eq!(
+            crc_response_token("crc_token", "consumer_secret"),
+            "sha256=Yiv7Gks/8XLZqlzwP8O/jDPfmURHnYJz611wFu448G8="
+        );
+        let signature = crc_response_token("{}", "consumer_secret");
+        assert!(verify_signature(
+            b"{}",
+            signature.as_bytes(),
+            "consumer_secret"
+        ));
+        assert!(!verify_signature(b"{}", signature.as_bytes(), "secret"));
+        assert!(!verify_signature(b"{}", b"sha256=", "consumer_secret"));
+    }
+
+    #[tokio::test]
+    async fn webhook_listener() {
+        let token = Token::new("ck", "cs", "ak", "as");
+        let mut listener = WebhookListener::bind(&([127, 0, 0, 1], 0).into(), &token).unwrap();
+        let uri = format!("http://{}/webhook", listener.local_addr());
+        let client = hyper_pkg::Client::new();
+
+        let event = r#"{"for_user_id":"2244994945","tweet_create_events":[]}"#;
+        let post = |signature: String| {
+            let req = Request::post(&uri)
+                .header(SIGNATURE_HEADER, signature)
+                .body(Body::from(event))
+                .unwrap();
+            client.request(req)
+        };
+
+        let crc = client.get(format!("{}?crc_token=foo", uri).parse().unwrap());
+        let forged = post(crc_response_token(event, "forged"));
+        let too_large = client.request(
+            Request::post(&uri)
+                .header(SIGNATURE_HEADER, crc_response_token(event, "cs"))
+                .body(Body::from(vec![b' '; MAX_BODY_LEN + 1]))
+                .unwrap(),
+        );
+        let valid = post(crc_response_token(event, "cs"));
+
+        let (json, crc, forged, too_large, valid) =
+            futures::join!(listener.next(), crc, forged, too_large, valid);
+        let crc = collect_body(crc.unwrap().into_body()).await.unwrap();
+        assert_eq!(
+            crc,
+            format!(
+                r#"{{"response_token":"{}"}}"#,
+                crc_response_token("foo", "cs")
+            )
+            .as_bytes()
+        );
+        assert_eq!(forged.unwrap().status(), StatusCode::FORBIDDEN);
+        assert_eq!(too_large.unwrap().status(), StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(valid.unwrap().status(), StatusCode::OK);
+        assert_eq!(&*json.unwrap().unwrap(), event);
+    }
+
+    #[test]
+    fn body_limit() {
+        let body = futures::executor::block_on(collect_body_limited(Body::from("{} "), 3));
+        assert_eq!(body.unwrap().unwrap(), b"{} ");
+        let body = futures::executor::block_on(collect_body_limited(Body::from("{}  "), 3));
+        assert_eq!(body.unwrap(), None);
+    }
+}